@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Describes how a handler may run alongside other requests. Since the
+/// engine currently dispatches requests one at a time, these limits are 
+/// always satisfied today, but they are enforced by the dispatcher so that
+/// handlers opting into them stay safe once requests run concurrently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Concurrency {
+    /// any number of invocations of the handler may run at once (the default)
+    Unlimited,
+    /// at most this many invocations of the handler may run at once.
+    /// `Limit(1)` serializes the handler with itself, but not with others.
+    Limit(usize),
+    /// the handler never runs alongside any other handler, including itself
+    Exclusive
+}
+
+#[derive(Default)]
+struct ActiveRequests {
+    total: usize,
+    exclusive: bool,
+    per_handler: HashMap<String, usize>
+}
+
+/// tracks in-flight requests, and blocks dispatch of a request
+/// until its handler's `Concurrency` allows it to run.
+pub(crate) struct ConcurrencyLimiter {
+    active: Mutex<ActiveRequests>,
+    released: Condvar
+}
+
+/// held for the duration of a handler's execution,
+/// releasing its slot when dropped.
+pub(crate) struct Permit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    call_name: String,
+    exclusive: bool
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> Self {
+        return ConcurrencyLimiter { active: Mutex::new(ActiveRequests::default()), released: Condvar::new() };
+    }
+
+    fn can_run(active: &ActiveRequests, call_name: &str, concurrency: Concurrency) -> bool {
+        if active.exclusive {
+            return false;
+        }
+        match concurrency {
+            Concurrency::Unlimited => true,
+            Concurrency::Limit(max) => active.per_handler.get(call_name).copied().unwrap_or(0) < max.max(1),
+            Concurrency::Exclusive => active.total == 0
+        }
+    }
+
+    /// blocks until the given handler is allowed to run
+    pub fn acquire(&self, call_name: &str, concurrency: Concurrency) -> Permit<'_> {
+        let mut active = self.active.lock().unwrap();
+        while !Self::can_run(&active, call_name, concurrency) {
+            active = self.released.wait(active).unwrap();
+        }
+        active.total += 1;
+        *active.per_handler.entry(call_name.to_string()).or_insert(0) += 1;
+        let exclusive = concurrency == Concurrency::Exclusive;
+        if exclusive {
+            active.exclusive = true;
+        }
+        return Permit { limiter: self, call_name: call_name.to_string(), exclusive: exclusive };
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap();
+        active.total -= 1;
+        if let Some(count) = active.per_handler.get_mut(&self.call_name) {
+            *count -= 1;
+        }
+        if self.exclusive {
+            active.exclusive = false;
+        }
+        self.limiter.released.notify_all();
+    }
+}
+
+/// advisory locks on files, by normalized path. A handler which reads, modifies and writes 
+/// back a file should hold its lock throughout, so that concurrent updates of the same file
+/// are serialized (rather than one overwriting the other), while different files proceed 
+/// in parallel. See `MessageContext::lock_file()`.
+#[derive(Default)]
+pub struct FileLocks {
+    locked: Mutex<HashSet<String>>,
+    released: Condvar
+}
+
+/// held while a file is being updated, releasing the file's lock when dropped
+pub struct FileLock {
+    locks: Arc<FileLocks>,
+    path: String
+}
+
+impl FileLocks {
+    /// blocks until no one else holds the given file's lock, and then takes it
+    pub fn lock(self: &Arc<Self>, path: &str) -> FileLock {
+        // differently written paths to the same file must share a lock
+        let path = crate::sandbox::normalize_path(path).unwrap_or(path.to_string());
+        let mut locked = self.locked.lock().unwrap();
+        while locked.contains(&path) {
+            locked = self.released.wait(locked).unwrap();
+        }
+        locked.insert(path.clone());
+        return FileLock { locks: self.clone(), path: path };
+    }
+
+    /// whether someone currently holds the given file's lock
+    pub fn is_locked(&self, path: &str) -> bool {
+        let path = crate::sandbox::normalize_path(path).unwrap_or(path.to_string());
+        return self.locked.lock().unwrap().contains(&path);
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        self.locks.locked.lock().unwrap().remove(&self.path);
+        self.locks.released.notify_all();
+    }
+}
+
+/// counts the handlers which are mid-execution. The count is atomic rather than locked, 
+/// so it can be read at any time, and is kept accurate by `BusyGuard`s.
+#[derive(Default, Clone)]
+pub struct BusyCounter {
+    running: Arc<AtomicUsize>
+}
+
+/// held while a handler (or background job) runs. 
+/// Dropping it marks the handler as no longer running.
+pub struct BusyGuard {
+    running: Arc<AtomicUsize>
+}
+
+impl BusyCounter {
+    /// marks a handler as running, until the returned guard is dropped
+    pub fn enter(&self) -> BusyGuard {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        return BusyGuard { running: self.running.clone() };
+    }
+
+    /// how many handlers are currently running
+    pub fn count(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Concurrency, ConcurrencyLimiter, FileLocks};
+    use std::sync::Arc;
+
+    fn is_available(limiter: &ConcurrencyLimiter, call_name: &str, concurrency: Concurrency) -> bool {
+        ConcurrencyLimiter::can_run(&limiter.active.lock().unwrap(), call_name, concurrency)
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let limiter = ConcurrencyLimiter::new();
+        let first = limiter.acquire("write_config", Concurrency::Limit(1));
+        assert!(!is_available(&limiter, "write_config", Concurrency::Limit(1)));
+        assert!(is_available(&limiter, "read_file", Concurrency::Unlimited));
+        assert!(!is_available(&limiter, "backup", Concurrency::Exclusive));
+        drop(first);
+        assert!(is_available(&limiter, "write_config", Concurrency::Limit(1)));
+
+        let exclusive = limiter.acquire("backup", Concurrency::Exclusive);
+        assert!(!is_available(&limiter, "read_file", Concurrency::Unlimited));
+        drop(exclusive);
+        assert!(is_available(&limiter, "read_file", Concurrency::Unlimited));
+    }
+
+    #[test]
+    fn file_locks_are_per_path() {
+        let locks = Arc::new(FileLocks::default());
+        let first = locks.lock("sd:/ultimate/config.json");
+        // a different file can be locked while the first is held
+        let other = locks.lock("sd:/ultimate/other.json");
+        assert!(locks.locked.lock().unwrap().contains("sd:/ultimate/config.json"));
+        drop(first);
+        drop(other);
+        assert!(locks.locked.lock().unwrap().is_empty());
+        // differently written paths share the lock of the file they name
+        let _again = locks.lock("sd:/ultimate/mods/../config.json");
+        assert!(locks.is_locked("sd:/ultimate/config.json"));
+        assert!(!locks.is_locked("sd:/ultimate/other.json"));
+    }
+}
@@ -0,0 +1,173 @@
+use serde::{Serialize, Deserialize};
+use crate::response::ArgumentSizeError;
+
+/// where temporary files are placed if no temp directory is configured
+pub const DEFAULT_TEMP_DIR: &str = "sd:/nx-request-handler/tmp";
+
+/// the backend's working directory, if none is configured
+pub const DEFAULT_CONFIG_DIR: &str = "sd:/nx-request-handler";
+
+/// the file which remembers the directory chosen by `set_config_dir`. This 
+/// has to live at a fixed location, so that it can be found again.
+pub const CONFIG_DIR_POINTER: &str = "sd:/nx-request-handler/config_dir.txt";
+
+/// the largest single argument accepted, in bytes, if no limit is configured
+pub const DEFAULT_MAX_ARGUMENT_SIZE: usize = 32 * 1024 * 1024;
+
+/// the largest total size of a request's arguments accepted, in bytes, if no limit is configured
+pub const DEFAULT_MAX_ARGUMENTS_SIZE: usize = 64 * 1024 * 1024;
+
+/// how long sending a message may keep failing, in seconds, before the 
+/// session is considered closed, if no timeout is configured
+pub const DEFAULT_SEND_TIMEOUT_SECS: u64 = 10;
+
+/// runtime settings for the `RequestEngine`, configured through
+/// its builder methods and visible to handlers via the `MessageContext`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EngineConfig {
+    /// the directory which `trash_file` moves files into. If `None`, `trash` in the working 
+    /// directory is used if one is set, and otherwise files are deleted permanently instead.
+    pub trash_dir: Option<String>,
+    /// the directory which `archive_file` moves files into, under a subdirectory for each date
+    pub archive_dir: Option<String>,
+    /// the directory which handlers stage temporary files in. 
+    /// If `None`, `DEFAULT_TEMP_DIR` is used.
+    pub temp_dir: Option<String>,
+    /// how many of the most recent errors to remember for `get_recent_errors`.
+    /// Error history is disabled when this is 0.
+    pub error_history_size: usize,
+    /// if set, `exit_session` acknowledges the request and then waits up to this many
+    /// milliseconds for the frontend to send `exit_session_ack` before closing.
+    pub exit_ack_timeout_ms: Option<u64>,
+    /// the directory which the `log` handler writes its rotating log file into, if any
+    pub log_dir: Option<String>,
+    /// the size, in bytes, beyond which log files are rotated
+    pub log_max_size: u64,
+    /// how many rotated log files to keep
+    pub log_backups: usize,
+    /// whether boolean responses are sent as the strings `"true"`/`"false"`
+    /// rather than as JSON booleans
+    pub string_booleans: bool,
+    /// the length (in bytes) from which responses are gzipped before being 
+    /// sent, or `None` to never compress them
+    pub compression_threshold: Option<usize>,
+    /// how many of the slowest handler invocations to remember for `slow_operations`.
+    /// Tracking is disabled when this is 0.
+    pub slow_operation_count: usize,
+    /// whether a failed `download_file` keeps its `.part` file (and reports its path),
+    /// rather than removing it
+    pub keep_partial_downloads: bool,
+    /// the file which `dir_hash` persists its per-file hash cache to, if any
+    pub hash_cache_file: Option<String>,
+    /// the backend's working directory, returned by `get_config_dir`. 
+    /// If `None`, `DEFAULT_CONFIG_DIR` is used.
+    pub config_dir: Option<String>,
+    /// the directories which handlers' path arguments must be within.
+    /// The sandbox is disabled when this is empty.
+    pub sandbox_roots: Vec<String>,
+    /// if set, only these calls are handled, and any others are rejected
+    pub allowed_calls: Option<Vec<String>>,
+    /// calls which are rejected even though a handler is registered for them
+    pub denied_calls: Vec<String>,
+    /// if set, files and directories in the temp directory which are older than this 
+    /// many seconds are removed when the engine starts
+    pub temp_cleanup_age_secs: Option<u64>,
+    /// whether handlers' path arguments are checked to exist (as the 
+    /// handler requires) before the handler is called
+    pub safe_mode: bool,
+    /// the free space (in bytes) which writing handlers must leave on the 
+    /// filesystem they write to, or `None` to not enforce a reserve
+    pub space_reserve: Option<u64>,
+    /// the largest single argument accepted, in bytes. 
+    /// If `None`, `DEFAULT_MAX_ARGUMENT_SIZE` is used.
+    pub max_argument_size: Option<usize>,
+    /// the largest total size of a request's arguments accepted, in bytes. 
+    /// If `None`, `DEFAULT_MAX_ARGUMENTS_SIZE` is used.
+    pub max_arguments_size: Option<usize>,
+    /// how many of the most recent results to keep for `get_result`.
+    /// Result replay is disabled when this is 0.
+    pub result_history_size: usize,
+    /// how long results are kept for `get_result`, in seconds
+    pub result_history_ttl_secs: u64,
+    /// how many of the most recent requests to record for `get_call_history`.
+    /// Recording is disabled when this is 0.
+    pub call_history_size: usize,
+    /// how long sending a message may keep failing, in seconds, before the session is
+    /// considered closed. If `None`, `DEFAULT_SEND_TIMEOUT_SECS` is used.
+    pub send_timeout_secs: Option<u64>,
+    /// if set, how often (in seconds) the engine checks that the session is still open
+    /// while waiting for a request, by sending it a `heartbeat` event
+    pub session_probe_secs: Option<u64>,
+}
+
+impl EngineConfig {
+    /// the directory to stage temporary files in
+    pub fn temp_dir(&self) -> String {
+        return match self.temp_dir {
+            Some(ref dir) => dir.clone(),
+            None => DEFAULT_TEMP_DIR.to_string()
+        }
+    }
+
+    /// how long sending a message may keep failing before the session is considered closed
+    pub fn send_timeout(&self) -> u64 {
+        self.send_timeout_secs.unwrap_or(DEFAULT_SEND_TIMEOUT_SECS)
+    }
+
+    /// a copy of this configuration with every setting which falls back to a 
+    /// default when unset filled in with the default, as the engine applies it
+    pub fn effective(&self) -> EngineConfig {
+        let mut config = self.clone();
+        config.temp_dir = Some(self.temp_dir());
+        config.max_argument_size = Some(self.max_argument_size.unwrap_or(DEFAULT_MAX_ARGUMENT_SIZE));
+        config.max_arguments_size = Some(self.max_arguments_size.unwrap_or(DEFAULT_MAX_ARGUMENTS_SIZE));
+        config.send_timeout_secs = Some(self.send_timeout());
+        return config;
+    }
+
+    /// checks the given arguments against the configured size limits
+    pub fn check_argument_sizes(&self, arguments: &Option<Vec<String>>) -> Result<(), ArgumentSizeError> {
+        let args = match arguments {
+            Some(args) => args,
+            None => return Ok(())
+        };
+        let max_argument_size = self.max_argument_size.unwrap_or(DEFAULT_MAX_ARGUMENT_SIZE);
+        if let Some((index, arg)) = args.iter().enumerate().find(|(_, arg)| arg.len() > max_argument_size) {
+            return Err(ArgumentSizeError { error: "argument_too_large".to_string(), argument: Some(index), size: arg.len(), limit: max_argument_size });
+        }
+        let max_arguments_size = self.max_arguments_size.unwrap_or(DEFAULT_MAX_ARGUMENTS_SIZE);
+        let total: usize = args.iter().map(|arg| arg.len()).sum();
+        if total > max_arguments_size {
+            return Err(ArgumentSizeError { error: "arguments_too_large".to_string(), argument: None, size: total, limit: max_arguments_size });
+        }
+        Ok(())
+    }
+
+    /// whether the given call may be handled, according to
+    /// the allowed and denied calls. Denial takes precedence.
+    pub fn is_call_permitted(&self, call_name: &str) -> bool {
+        if self.denied_calls.iter().any(|denied| denied == call_name) {
+            return false;
+        }
+        return match self.allowed_calls {
+            Some(ref allowed) => allowed.iter().any(|allowed| allowed == call_name),
+            None => true
+        }
+    }
+
+    /// the backend's working directory, as configured (ignoring `set_config_dir`)
+    pub fn config_dir(&self) -> String {
+        return match self.config_dir {
+            Some(ref dir) => dir.clone(),
+            None => DEFAULT_CONFIG_DIR.to_string()
+        }
+    }
+}
+
+/// reads the directory persisted by `set_config_dir`, if any
+pub(crate) fn load_config_dir() -> Option<String> {
+    return match std::fs::read_to_string(CONFIG_DIR_POINTER) {
+        Ok(dir) if !dir.trim().is_empty() => Some(dir.trim().to_string()),
+        _ => None
+    }
+}
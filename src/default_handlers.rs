@@ -2,9 +2,12 @@ use std::path::Path;
 use crate::*;
 use std::fs;
 use smashnet::curl::Curler;
-//use walkdir::*;
-use std::io::Read;
-use crate::response::{DirTree, PathEntry, PathList};
+use walkdir::WalkDir;
+use std::io::{Read, Write};
+use sha2::Digest;
+use base64::Engine;
+use crate::state::{unix_timestamp, CachedHash};
+use crate::response::{Capabilities, CompressionResult, Connectivity, DecodedText, DirEntries, DirTree, DiffLine, DirStats, DuplicateGroup, ExtensionStats, EngineSettings, Feasibility, FileContents, FileSize, FollowUpdate, HandlerInfo, InstallCheck, IoBenchmark, Manifest, ManifestDiff, ManifestEntry, MergeResult, PathBatch, PathEntry, PathList, PathValidation, SearchPage, Schema, SplitResult, SupportBundle, UrlResult, VerifiedDownload, ZipSafetyReport};
 
 fn readDirAll(dir: String, tree: &mut DirTree) {
     //let tabs = "";
@@ -14,19 +17,36 @@ fn readDirAll(dir: String, tree: &mut DirTree) {
         let path = pathmaybe.unwrap();
         let fullpath = path.path();
         let file_name = format!("{}", path.file_name().into_string().unwrap());
-        if path.metadata().unwrap().is_file() {
+        let metadata = path.metadata().unwrap();
+        if metadata.is_file() {
             //println!("File: {}", file_name);
             tree.files.push(file_name);
+            tree.file_sizes.push(metadata.len());
+            tree.size += metadata.len();
         } else {
             //println!("Directory: {}", file_name);
-            let mut subtree = DirTree{name: file_name, files: Vec::new(), dirs: Vec::new()};
+            let mut subtree = DirTree::new(file_name);
             readDirAll(fullpath.into_os_string().into_string().unwrap(), &mut subtree);
+            tree.size += subtree.size;
             tree.dirs.push(subtree);
         }
     }
     
 }
 
+/// returns the arguments of the request, ensuring that between `min` and `max`
+/// of them were provided. Used by handlers which accept optional arguments.
+fn get_args<'a>(context: &'a MessageContext, min: usize, max: usize) -> Result<&'a Vec<String>, String> {
+    let args = match context.arguments.as_ref() {
+        Some(args) => args,
+        None => return Err(format!("No arguments were provided for {}", context.call_name))
+    };
+    if args.len() < min || args.len() > max {
+        return Err(format!("Incorrect number of arguments were provided for {}", context.call_name));
+    }
+    Ok(args)
+}
+
 pub fn pong() -> Result<String, String> {
     Ok("pong from switch!".to_string())
 }
@@ -39,33 +59,363 @@ pub fn read_file(context: &mut MessageContext) -> Result<String, String> {
     if !exists {
         return Err(format!("requested file ({}) does not exist!", path));
     } else {
-        return match fs::read_to_string(path) {
-            Ok(data) => Ok(data),
-            Err(e) => Err(format!("While reading file, {}", e))
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => return Err(format!("While reading file, {}", e))
+        };
+        // report invalid text explicitly, rather than the opaque io error
+        // that read_to_string() would give us.
+        return match String::from_utf8(data) {
+            Ok(text) => Ok(text),
+            Err(e) => Err(format!(
+                "file {} is not valid UTF-8 (invalid data at byte {}); use read_file_full, which returns binary files as base64",
+                path, e.utf8_error().valid_up_to()))
         }
     }
 }
 
-pub fn download_file(context: &mut MessageContext) -> Result<String, String> {
+/// the most content which `read_file_full` returns, to keep responses reasonable
+const MAX_FULL_READ_SIZE: u64 = 8 * 1024 * 1024;
+
+pub fn read_file_full(context: &mut MessageContext) -> Result<String, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    let md = match file.metadata() {
+        Ok(md) => md,
+        Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", path, e))
+    };
+    let mut data = Vec::new();
+    if let Err(e) = file.take(MAX_FULL_READ_SIZE).read_to_end(&mut data) {
+        return Err(format!("While reading file, {}", e));
+    }
+    let partial = md.len() > data.len() as u64;
+
+    // text is returned as is, and anything else as base64. A partial read may end 
+    // partway through a character, which doesn't make the file binary.
+    let text_len = match std::str::from_utf8(&data) {
+        Ok(_) => Some(data.len()),
+        Err(e) if partial && e.error_len().is_none() => Some(e.valid_up_to()),
+        Err(_) => None
+    };
+    let (content, encoding) = match text_len {
+        Some(len) => (String::from_utf8_lossy(&data[..len]).into_owned(), "utf8"),
+        None => (base64::engine::general_purpose::STANDARD.encode(&data), "base64")
+    };
+    let contents = FileContents {
+        content: content,
+        encoding: encoding.to_string(),
+        size: md.len(),
+        modified: md.modified().ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|time| time.as_secs())
+            .unwrap_or(0),
+        md5: hash_file(&path, "md5")?,
+        partial: partial
+    };
+    return match serde_json::to_string(&contents) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json FileContents. Error: {}", e))
+    }
+}
+
+/// the response of `read_file_if_changed` when the file still has the known hash
+const NOT_MODIFIED: &str = "not_modified";
+
+pub fn read_file_if_changed(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let known_hash = args[1].trim().to_lowercase();
+    if !Path::new(&path).exists() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+
+    // the known hash may be either an md5 (as from get_md5) or a sha256 digest
+    let algorithm = match known_hash.len() {
+        64 => "sha256",
+        _ => "md5"
+    };
+    if hash_file(&path, algorithm)? == known_hash {
+        return Ok(NOT_MODIFIED.to_string());
+    }
+    read_file(context)
+}
+
+/// looks up a text encoding by its label, such as `utf-16le` or `shift_jis`
+fn get_encoding(label: &str) -> Result<&'static encoding_rs::Encoding, String> {
+    return match encoding_rs::Encoding::for_label(label.trim().as_bytes()) {
+        Some(encoding) => Ok(encoding),
+        None => Err(format!("unknown text encoding: {}", label))
+    }
+}
+
+pub fn read_line(context: &mut MessageContext) -> Result<String, String> {
     let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let line_number = match args[1].parse::<usize>() {
+        Ok(number) if number > 0 => number,
+        _ => return Err(format!("invalid line number: {} (lines are numbered from 1)", args[1]))
+    };
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return Err(format!("requested file ({}) does not exist!", path))
+    };
+
+    // stream the lines, rather than reading the whole file
+    let lines = std::io::BufRead::lines(std::io::BufReader::new(file));
+    let mut count = 0;
+    for line in lines {
+        count += 1;
+        if count == line_number {
+            return match line {
+                Ok(line) => Ok(line.trim_end_matches('\r').to_string()),
+                Err(e) => Err(format!("While reading file, {}", e))
+            }
+        }
+    }
+    Err(format!("line {} is past the end of the file ({} lines)", line_number, count))
+}
+
+pub fn read_file_encoded(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let encoding = get_encoding(&args[1])?;
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(&data);
+    if had_errors {
+        return Err(format!("file {} is not valid {}", path, encoding.name()));
+    }
+    Ok(text.into_owned())
+}
+
+pub fn read_file_no_bom(context: &mut MessageContext) -> Result<String, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+
+    // files without a byte order mark are assumed to be UTF-8
+    let (encoding, bom_length) = encoding_rs::Encoding::for_bom(&data).unwrap_or((encoding_rs::UTF_8, 0));
+    let (text, had_errors) = encoding.decode_without_bom_handling(&data[bom_length..]);
+    if had_errors {
+        return Err(format!("file {} is not valid {}", path, encoding.name()));
+    }
+    let decoded = DecodedText { content: text.into_owned(), encoding: encoding.name().to_string(), bom: bom_length > 0 };
+    return match serde_json::to_string(&decoded) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json DecodedText. Error: {}", e))
+    }
+}
+
+pub fn download_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 5)?;
     let url = args[0].clone();
     let location = args[1].clone();
-    
+    // the frontend may know the size (such as from a manifest) when the server doesn't say
+    let expected_total = match args.get(2).filter(|total| !total.is_empty()).map(|total| total.parse::<u64>()) {
+        None => None,
+        Some(Ok(total)) if total > 0 => Some(total as f64),
+        Some(_) => return Err(format!("invalid expected total: {} (must be a positive number of bytes)", args[2]))
+    };
+    // optionally hash the download, and compare it to an expected hash. Like the 
+    // expected total, an empty algorithm or hash means none was given.
+    let hasher = match args.get(3).filter(|algorithm| !algorithm.is_empty()) {
+        Some(algorithm) => Some(std::cell::RefCell::new(StreamHasher::new(algorithm)?)),
+        None => None
+    };
+    let expected_hash = args.get(4).map(|hash| hash.trim().to_lowercase()).filter(|hash| !hash.is_empty());
+    // the download's size isn't known up front, so check the reserve both before and after
+    check_space_reserve(context.config.space_reserve, &location, 0)?;
+
+    // download into a .part file, so that a failed download never leaves 
+    // a truncated file at the destination
+    let part_path = format!("{}.part", location);
+    // the progress callback can't fail the download, so the first hashing error is kept for afterwards
+    let hash_error: std::cell::RefCell<Option<String>> = std::cell::RefCell::new(None);
     let progress = |total: f64, current: f64| {
+        let total = match expected_total {
+            // once the download outgrows the expected total, it was evidently wrong
+            Some(expected) if total <= 0.0 && current <= expected => expected,
+            _ => total
+        };
+        // Curler doesn't expose the downloaded bytes, only the file it writes them to, so 
+        // the hash reads back what has been written since the last update. This is not a
+        // single pass: every byte is read back from the SD card once more.
+        if let Some(hasher) = &hasher {
+            if hash_error.borrow().is_none() {
+                if let Err(e) = hasher.borrow_mut().catch_up(&part_path) {
+                    *hash_error.borrow_mut() = Some(e);
+                }
+            }
+        }
         context.send_progress(Progress::new(
         "Downloading".to_string(), 
         "downloading a file".to_string(), 
         current/total));
     };
+    let result = Curler::new()
+        .progress_callback(&progress)
+        .download(url, part_path.clone());
+
+    if let Err(e) = result {
+        if !context.config.keep_partial_downloads {
+            let _ = fs::remove_file(&part_path);
+            return Err(format!("Error during download, error name: {:?}", e));
+        }
+        let size = fs::metadata(&part_path).map(|md| md.len()).unwrap_or(0);
+        return Err(format!("Error during download, error name: {:?}. The partial download ({} bytes) was kept at {}", e, size, part_path));
+    }
+    if let Err(e) = check_space_reserve(context.config.space_reserve, &location, 0) {
+        let _ = fs::remove_file(&part_path);
+        return Err(e);
+    }
+    if let Some(e) = hash_error.into_inner() {
+        let _ = fs::remove_file(&part_path);
+        return Err(format!("Could not hash the download while it was written. Reason: {}", e));
+    }
+    let hash = match hasher.map(|hasher| hasher.into_inner()) {
+        Some(mut hasher) => {
+            // hash whatever was written after the last progress update (or all of it, 
+            // if the .part file couldn't be opened while it was being written)
+            if let Err(e) = hasher.catch_up(&part_path) {
+                let _ = fs::remove_file(&part_path);
+                return Err(e);
+            }
+            Some(hasher.finish())
+        },
+        None => None
+    };
+    if let (Some(hash), Some(expected)) = (&hash, &expected_hash) {
+        if hash != expected {
+            let _ = fs::remove_file(&part_path);
+            return Err(format!("the downloaded file's hash is {}, but {} was expected", hash, expected));
+        }
+    }
+    // some filesystems refuse to rename over an existing file
+    if fs::rename(&part_path, &location).is_err() {
+        let _ = fs::remove_file(&location);
+        if let Err(e) = fs::rename(&part_path, &location) {
+            let _ = fs::remove_file(&part_path);
+            return Err(format!("Could not move download into place. Reason: {:?}", e));
+        }
+    }
+    context.publish("download_complete", &location);
+    return match hash {
+        Some(hash) => Ok(format!("File downloaded successfully! Hash: {}", hash)),
+        None => Ok("File downloaded successfully!".to_string())
+    }
+}
+
+pub fn download_and_verify(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let url = args[0].clone();
+    let checksum_url = args[1].clone();
+    let location = args[2].clone();
+    let mut outcome = VerifiedDownload { path: location.clone(), algorithm: None, expected: None, actual: None, failed_step: None, error: None };
+    // failures are reported with the outcome so far, so the frontend can tell which step failed
+    let fail = |mut outcome: VerifiedDownload, step: &str, error: String| {
+        outcome.failed_step = Some(step.to_string());
+        outcome.error = Some(error.clone());
+        Err(serde_json::to_string(&outcome).unwrap_or(error))
+    };
+
+    let checksums = match Curler::new().get(checksum_url) {
+        Ok(body) => body,
+        Err(e) => return fail(outcome, "checksum_download", format!("Error during get: {}", e))
+    };
+    let file_name = url.rsplit('/').next().unwrap_or("").split('?').next().unwrap_or("").to_string();
+    let expected = match parse_checksum(&checksums, &file_name) {
+        Some(expected) => expected,
+        None => return fail(outcome, "checksum_parse", "no md5 or sha256 checksum was found in the checksum file".to_string())
+    };
+    let algorithm = if expected.len() == 32 { "md5" } else { "sha256" };
+    outcome.algorithm = Some(algorithm.to_string());
+    outcome.expected = Some(expected.clone());
 
+    if let Err(e) = check_space_reserve(context.config.space_reserve, &location, 0) {
+        return fail(outcome, "download", e);
+    }
+    let progress = |total: f64, current: f64| {
+        context.send_progress(Progress::new(
+        "Downloading".to_string(), 
+        "downloading a file".to_string(), 
+        current/total));
+    };
+    let part_path = format!("{}.part", location);
     let result = Curler::new()
         .progress_callback(&progress)
-        .download(url, location);
+        .download(url, part_path.clone());
+    if let Err(e) = result {
+        let _ = fs::remove_file(&part_path);
+        return fail(outcome, "download", format!("Error during download, error name: {:?}", e));
+    }
 
-    return match result {
-        Ok(()) => Ok("File downloaded successfully!".to_string()),
-        Err(e) => Err(format!("Error during download, error name: {:?}", e))
+    let actual = match hash_file(&part_path, algorithm) {
+        Ok(actual) => actual,
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            return fail(outcome, "verify", e);
+        }
+    };
+    outcome.actual = Some(actual.clone());
+    if actual != expected {
+        let _ = fs::remove_file(&part_path);
+        return fail(outcome, "verify", format!("the downloaded file's {} is {}, but {} was expected", algorithm, actual, expected));
+    }
+
+    // some filesystems refuse to rename over an existing file
+    if fs::rename(&part_path, &location).is_err() {
+        let _ = fs::remove_file(&location);
+        if let Err(e) = fs::rename(&part_path, &location) {
+            let _ = fs::remove_file(&part_path);
+            return fail(outcome, "move", format!("Could not move download into place. Reason: {:?}", e));
+        }
+    }
+    context.publish("download_complete", &location);
+    return match serde_json::to_string(&outcome) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json VerifiedDownload. Error: {}", e))
+    }
+}
+
+/// finds the checksum for the given file name in the contents of a checksum file, which may 
+/// be a bare hash, `md5sum`/`sha256sum` output (`<hash>  <name>` per line), or BSD-style 
+/// output (`MD5 (<name>) = <hash>`). If no line names the file, the first hash is used.
+fn parse_checksum(contents: &str, file_name: &str) -> Option<String> {
+    let is_hash = |word: &str| (word.len() == 32 || word.len() == 64) && word.chars().all(|c| c.is_ascii_hexdigit());
+    let mut first = None;
+    for line in contents.lines() {
+        let line = line.trim();
+        let (hash, name) = match line.rsplit_once(" = ") {
+            // BSD-style, such as `MD5 (file.zip) = <hash>`
+            Some((label, hash)) => (hash.trim(), label.split_once('(').map(|(_, name)| name.trim_end_matches(')')).unwrap_or("")),
+            None => {
+                let mut words = line.split_whitespace();
+                (words.next().unwrap_or(""), words.next().unwrap_or("").trim_start_matches('*'))
+            }
+        };
+        if !is_hash(hash) {
+            continue;
+        }
+        if !file_name.is_empty() && name.rsplit('/').next() == Some(file_name) {
+            return Some(hash.to_lowercase());
+        }
+        first = first.or(Some(hash.to_lowercase()));
     }
+    first
 }
 
 pub fn delete_file(context: &mut MessageContext) -> Result<String, String> {
@@ -83,250 +433,3780 @@ pub fn delete_file(context: &mut MessageContext) -> Result<String, String> {
     }
 }
 
-pub fn write_file(context: &mut MessageContext) -> Result<String, String> {
-    let args = context.arguments.as_ref().unwrap();
-    let path = args[0].clone();
-    let exists = Path::new(&path).exists();
-    if exists {
-        // delete existing file, if present
-        match fs::remove_file(path.clone()) {
-            Ok(_) => println!("Deleted existing file successfully."),
-            Err(e) => return Err(format!("Could not delete existing file! Reason: {:?}", e))
-        }
-    } 
+/// the suffix of the sidecar file recording where a trashed file came from
+const TRASH_INFO_EXTENSION: &str = ".trashinfo";
 
-    return match fs::write(path, args[1].clone()) {
-        Ok(_) => Ok("The file was written successfully".to_string()),
-        Err(e) => Err(format!("Could not write file. Reason: {:?}", e))
+/// moves a file, falling back to copy and delete when a plain rename
+/// is not possible (such as across different mount points).
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
     }
+    copy_file(from, to, true)?;
+    fs::remove_file(from)
 }
 
-pub fn get_md5(context: &mut MessageContext) -> Result<String, String> {
+/// copies a file like `fs::copy`, which gives the copy the current time as its modified 
+/// time, unless `preserve_timestamps` is set, in which case it keeps the source's modified 
+/// time (where the filesystem supports setting it, otherwise the copy still succeeds).
+fn copy_file(from: &Path, to: &Path, preserve_timestamps: bool) -> std::io::Result<u64> {
+    let copied = fs::copy(from, to)?;
+    if preserve_timestamps {
+        let _ = fs::metadata(from)
+            .and_then(|md| md.modified())
+            .and_then(|modified| fs::OpenOptions::new().write(true).open(to)?.set_modified(modified));
+    }
+    Ok(copied)
+}
+
+pub fn trash_file(context: &mut MessageContext) -> Result<String, String> {
     let args = context.arguments.as_ref().unwrap();
     let path = args[0].clone();
-    let exists = Path::new(&path).exists();
-    if !exists {
+    if !Path::new(&path).is_file() {
         return Err(format!("requested file ({}) does not exist!", path));
-    } else {
-        // read the file
-        let data = match fs::read(path) {
-            Ok(data) => data,
-            Err(e) => {
-                return Err(format!("while reading file, {:?}", e));
+    }
+
+    let trash_dir = match context.trash_dir() {
+        Some(dir) => dir,
+        None => {
+            log::warn!("no trash directory is configured, deleting {} permanently.", path);
+            return match fs::remove_file(&path) {
+                Ok(_) => Ok("No trash directory is configured, so the file was deleted permanently".to_string()),
+                Err(e) => Err(format!("{}", e))
             }
-        };
-        // compute the md5 and return the value
-        let digest = md5::compute(data);
-        return Ok(format!("{:x}", digest));
+        }
+    };
+    if let Err(e) = fs::create_dir_all(&trash_dir) {
+        return Err(format!("Could not create trash directory {}. Reason: {:?}", trash_dir, e));
     }
-}
 
-pub fn unzip(context: &mut MessageContext) -> Result<String, String> {
-    let args = context.arguments.as_ref().unwrap();
-    let filepath = args[0].clone();
-    let destination = args[1].clone();
+    // timestamp the trashed name, and count up in case of collisions
+    let file_name = Path::new(&path).file_name().unwrap().to_string_lossy().to_string();
+    let timestamp = unix_timestamp();
+    let mut trashed_name = format!("{}_{}", timestamp, file_name);
+    let mut counter = 1;
+    while Path::new(&trash_dir).join(&trashed_name).exists() {
+        trashed_name = format!("{}_{}_{}", timestamp, counter, file_name);
+        counter += 1;
+    }
 
-    if !Path::new(&filepath).exists() {
-        return Err(format!("file {} does not exist!", filepath));
+    let trashed_path = Path::new(&trash_dir).join(&trashed_name);
+    if let Err(e) = move_file(Path::new(&path), &trashed_path) {
+        return Err(format!("Could not move file to trash. Reason: {:?}", e));
     }
-    if !Path::new(&filepath).is_file() {
-        return Err(format!("path {} is not a file!", filepath));
+    let info_path = Path::new(&trash_dir).join(format!("{}{}", trashed_name, TRASH_INFO_EXTENSION));
+    if let Err(e) = fs::write(info_path, &path) {
+        log::warn!("could not record original location of {}: {:?}", path, e);
     }
+    Ok(trashed_name)
+}
 
-    if !Path::new(&destination).exists() {
-        return Err(format!("path {} does not exist!", destination));
+pub fn archive_file(context: &mut MessageContext) -> Result<String, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
     }
-    if !Path::new(&destination).is_dir() {
-        return Err(format!("path {} is not a directory!", destination));
+    let archive_dir = match context.config.archive_dir {
+        Some(ref dir) => Path::new(dir).join(date_string(unix_timestamp())),
+        None => return Err("No archive directory is configured!".to_string())
+    };
+    if let Err(e) = fs::create_dir_all(&archive_dir) {
+        return Err(format!("Could not create archive directory {}. Reason: {:?}", archive_dir.display(), e));
     }
 
-    let mut zip = match unzipper::get_zip_archive(&filepath) {
-        Ok(zip) => zip,
-        Err(_) => return Err("Could not parse zip file!".to_string())
-    };
+    // keep the file's name, and count up in case of collisions
+    let source = Path::new(&path);
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = source.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    let mut archived_path = archive_dir.join(format!("{}{}", stem, extension));
+    let mut counter = 1;
+    while archived_path.exists() {
+        archived_path = archive_dir.join(format!("{}_{}{}", stem, counter, extension));
+        counter += 1;
+    }
 
-    let count = zip.len();
+    if let Err(e) = move_file(source, &archived_path) {
+        return Err(format!("Could not move file to archive. Reason: {:?}", e));
+    }
+    Ok(archived_path.display().to_string())
+}
 
-    for file_no in 0..count {
-        let mut file = zip.by_index(file_no).unwrap();
-        if !file.is_file() {
-            continue;
-        }
+/// formats the given unix timestamp as a UTC date, such as `2024-06-01`
+fn date_string(timestamp: u64) -> String {
+    // converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
 
-        context.send_progress(Progress::new(
-            "Extracting".to_string(), 
-            format!("{}", file.name()), 
-            (file_no as f64)/(count as f64)));
-        
-        let path = Path::new(&destination).join(file.name());
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent);
-        }
+pub fn restore_trashed(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let trashed_name = args[0].clone();
+    check_trashed_name(&trashed_name)?;
+    let trash_dir = match context.trash_dir() {
+        Some(dir) => dir,
+        None => return Err("No trash directory is configured!".to_string())
+    };
 
-        let mut file_data = vec![];
-        file.read_to_end(&mut file_data).unwrap();
-        std::fs::write(path, file_data).unwrap();
+    let trashed_path = Path::new(&trash_dir).join(&trashed_name);
+    if !trashed_path.is_file() {
+        return Err(format!("trashed file {} does not exist!", trashed_name));
     }
+    let info_path = Path::new(&trash_dir).join(format!("{}{}", trashed_name, TRASH_INFO_EXTENSION));
 
-    Ok("unzip succeeded".to_string())
+    // restore to the given destination, or else to where the file came from
+    let destination = match args.get(1) {
+        Some(destination) => destination.clone(),
+        None => trashed_original_location(context.config, &info_path, &trashed_name)?
+    };
+    if Path::new(&destination).exists() {
+        return Err(format!("path {} already exists!", destination));
+    }
+    if let Some(parent) = Path::new(&destination).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    if let Err(e) = move_file(&trashed_path, Path::new(&destination)) {
+        return Err(format!("Could not restore file. Reason: {:?}", e));
+    }
+    let _ = fs::remove_file(info_path);
+    Ok(destination)
 }
 
-pub fn mkdir(context: &mut MessageContext) -> Result<String, String> {
-    let dir = &context.arguments.as_ref().unwrap()[0];
-    return match std::fs::create_dir_all(dir) {
-        Ok(ok) => Ok(format!("{:?}", ok)),
-        Err(err) => Ok(format!("{:?}", err)),
+/// checks that a trashed name (as returned by `trash_file`) is a single file name,
+/// so that it can't refer to anything outside of the trash directory
+fn check_trashed_name(name: &str) -> Result<(), String> {
+    let mut components = Path::new(name).components();
+    let is_single_name = matches!((components.next(), components.next()), (Some(std::path::Component::Normal(_)), None));
+    if !is_single_name || name.contains('/') || name.contains('\\') || name.contains("..") {
+        return Err(format!("{} is not the name of a trashed file", name));
+    }
+    Ok(())
+}
+
+/// reads where a trashed file came from, from its sidecar file. The location 
+/// is checked against the sandbox, since the sidecar file may have been edited.
+fn trashed_original_location(config: &EngineConfig, info_path: &Path, trashed_name: &str) -> Result<String, String> {
+    let original = match fs::read_to_string(info_path) {
+        Ok(original) => original.trim().to_string(),
+        Err(_) => return Err(format!("original location of {} is unknown, a destination must be given", trashed_name))
     };
+    crate::sandbox::check_path(config, &original)
 }
 
-pub fn list_all_files(context: &mut MessageContext) -> Result<String, String> {
-    let args = context.arguments.as_ref().unwrap();
-    let path = args[0].clone();
-    if !Path::new(&path).exists() {
-        return Err(format!("path {} does not exist!", path));
-    }
-    if !Path::new(&path).is_dir() {
-        return Err(format!("path {} is not a directory!", path));
+pub fn empty_trash(context: &mut MessageContext) -> Result<String, String> {
+    let trash_dir = match context.trash_dir() {
+        Some(dir) => dir,
+        None => return Err("No trash directory is configured!".to_string())
+    };
+    if !Path::new(&trash_dir).exists() {
+        return Ok("0".to_string());
     }
 
-    let mut subtree = DirTree{name: path.clone(), files: Vec::new(), dirs: Vec::new()};
-    readDirAll(path, &mut subtree);
-    
-    let json = match serde_json::to_string(&subtree) {
-        Ok(val) => val,
-        Err(e) => {
-            return Err(format!("Could not serialize to json DirTree. Error: {}", e)); 
-        }
+    let entries = match fs::read_dir(&trash_dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Could not read trash directory. Reason: {:?}", e))
     };
-    //println!("replying to list_all_files with a string of size: {}", json.len());
-    Ok(json)
+    let mut count = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let result = match path.is_dir() {
+            true => fs::remove_dir_all(&path),
+            false => fs::remove_file(&path)
+        };
+        if let Err(e) = result {
+            return Err(format!("Could not delete {}. Reason: {:?}", path.display(), e));
+        }
+        if !path.to_string_lossy().ends_with(TRASH_INFO_EXTENSION) {
+            count += 1;
+        }
+    }
+    Ok(count.to_string())
 }
 
-pub fn file_exists(context: &mut MessageContext) -> Result<String, String> {
+pub fn write_file(context: &mut MessageContext) -> Result<String, String> {
     let args = context.arguments.as_ref().unwrap();
     let path = args[0].clone();
-    let exists = Path::new(&path).exists() && Path::new(&path).is_file();
-    Ok(exists.to_string())
-}
+    check_space_reserve(context.config.space_reserve, &path, args[1].len() as u64)?;
+    let exists = Path::new(&path).exists();
+    if exists {
+        // delete existing file, if present
+        match fs::remove_file(path.clone()) {
+            Ok(_) => println!("Deleted existing file successfully."),
+            Err(e) => return Err(format!("Could not delete existing file! Reason: {:?}", e))
+        }
+    } 
 
-pub fn dir_exists(context: &mut MessageContext) -> Result<String, String> {
-    let args = context.arguments.as_ref().unwrap();
-    let path = args[0].clone();
-    let exists = Path::new(&path).exists() && Path::new(&path).is_dir();
-    Ok(exists.to_string())
+    return match fs::write(path, args[1].clone()) {
+        Ok(_) => Ok("The file was written successfully".to_string()),
+        Err(e) => Err(format!("Could not write file. Reason: {:?}", e))
+    }
 }
 
-pub fn list_dir(context: &mut MessageContext) -> Result<String, String> {
+pub fn write_file_sync(context: &mut MessageContext) -> Result<String, String> {
     let args = context.arguments.as_ref().unwrap();
     let path = args[0].clone();
-    if !Path::new(&path).exists() {
-        return Err(format!("path {} does not exist!", path));
-    }
-    if !Path::new(&path).is_dir() {
-        return Err(format!("path {} is not a directory!", path));
+    check_space_reserve(context.config.space_reserve, &path, args[1].len() as u64)?;
+    if crate::platform::is_read_only(&path) {
+        return Err(format!("path {} is read-only", path));
     }
 
-    let paths = fs::read_dir(path).unwrap();
-    //println!("Paths...");
-    let mut vec = Vec::new();
-    for entry in paths {
-        let fullpath = entry.unwrap().path().display().to_string();
-        //println!("Path: {}", fullpath);
-        let md = fs::metadata(fullpath.clone()).unwrap();
-        let kind = match md.is_file() {
-            true => 0,
-            false => 1
-        };
-        let path_entry = PathEntry{path: fullpath, kind: kind};
-        vec.push(path_entry);
+    // write and flush a temporary file first, so that a power loss leaves either
+    // the old file or the complete new one
+    let tmp_path = format!("{}.tmp", path);
+    let written = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(args[1].as_bytes())?;
+            file.sync_all()
+        });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Could not write file. Reason: {:?}", e));
     }
-    let path_list = PathList{list: vec};
-    let json = match serde_json::to_string(&path_list) {
+    if fs::rename(&tmp_path, &path).is_err() {
+        // some filesystems refuse to rename over an existing file
+        let _ = fs::remove_file(&path);
+        if let Err(e) = fs::rename(&tmp_path, &path) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(format!("Could not replace file. Reason: {:?}", e));
+        }
+    }
+
+    // also flush the directory entry where supported, so the rename itself is durable
+    if let Some(parent) = Path::new(&path).parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok("The file was written and synced successfully".to_string())
+}
+
+/// checks that writing `needed` more bytes to the given path would leave at least the 
+/// configured reserve of free space (see `with_space_reserve()`), returning a `low_space`
+/// error if not. Writes are allowed when there is no reserve, or the free space is unknown.
+fn check_space_reserve(reserve: Option<u64>, path: &str, needed: u64) -> Result<(), String> {
+    let (reserve, free) = match (reserve, platform::free_space(path)) {
+        (Some(reserve), Some(free)) => (reserve, free),
+        _ => return Ok(())
+    };
+    if free < needed.saturating_add(reserve) {
+        return Err(format!("low_space: writing {} bytes to {} would leave less than the reserved {} bytes free ({} bytes are free)", needed, path, reserve, free));
+    }
+    Ok(())
+}
+
+/// writes the given data to a temporary file next to `path`, and then
+/// moves it over the original, so a failure never leaves a half-written file.
+fn write_atomic(path: &str, data: &[u8]) -> Result<(), String> {
+    if crate::platform::is_read_only(path) {
+        return Err(format!("path {} is read-only", path));
+    }
+    let tmp_path = format!("{}.tmp", path);
+    if let Err(e) = fs::write(&tmp_path, data) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Could not write temporary file. Reason: {:?}", e));
+    }
+    if fs::rename(&tmp_path, path).is_ok() {
+        return Ok(());
+    }
+    // some filesystems refuse to rename over an existing file,
+    // so remove the original and try once more.
+    let _ = fs::remove_file(path);
+    return match fs::rename(&tmp_path, path) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(format!("Could not replace file. Reason: {:?}", e))
+        }
+    }
+}
+
+pub fn normalize_line_endings(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let ending = match args[1].to_lowercase().as_str() {
+        "lf" => "\n",
+        "crlf" => "\r\n",
+        other => return Err(format!("unknown line ending style: {} (expected lf or crlf)", other))
+    };
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    let _lock = context.lock_file(&path);
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return Err(format!("file {} is not valid UTF-8, refusing to modify it", path))
+    };
+
+    let mut changed = 0;
+    let mut output = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (content, old_ending) = match line.strip_suffix("\r\n") {
+            Some(content) => (content, "\r\n"),
+            None => match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, "")
+            }
+        };
+        output.push_str(content);
+        // the final line may not have an ending at all, leave it that way
+        if !old_ending.is_empty() {
+            if old_ending != ending {
+                changed += 1;
+            }
+            output.push_str(ending);
+        }
+    }
+
+    if changed > 0 {
+        write_atomic(&path, output.as_bytes())?;
+    }
+    Ok(changed.to_string())
+}
+
+pub fn patch_bytes(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 3, 4)?;
+    let path = args[0].clone();
+    let offset = match args[1].parse::<u64>() {
+        Ok(offset) => offset,
+        Err(_) => return Err(format!("invalid offset: {}", args[1]))
+    };
+    let bytes = match base64::engine::general_purpose::STANDARD.decode(args[2].trim()) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(format!("Could not decode base64 bytes. Error: {}", e))
+    };
+    let extend = parse_flag(args.get(3));
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    if crate::platform::is_read_only(&path) {
+        return Err(format!("path {} is read-only", path));
+    }
+    let _lock = context.lock_file(&path);
+
+    let mut file = match fs::OpenOptions::new().write(true).open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    let size = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", path, e))
+    };
+    let end = offset.saturating_add(bytes.len() as u64);
+    if end > size {
+        if !extend {
+            return Err(format!("patching {} bytes at offset {} would extend {} beyond its size of {} bytes", bytes.len(), offset, path, size));
+        }
+        check_space_reserve(context.config.space_reserve, &path, end - size)?;
+    }
+
+    // only the patched bytes are written, the rest of the file is left as it is
+    let written = std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(&bytes))
+        .and_then(|_| file.flush());
+    if let Err(e) = written {
+        return Err(format!("Could not write to file {}. Reason: {:?}", path, e));
+    }
+    Ok(bytes.len().to_string())
+}
+
+pub fn allocate_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let size = match args[1].parse::<u64>() {
+        Ok(size) => size,
+        Err(_) => return Err(format!("invalid size: {}", args[1]))
+    };
+    if crate::platform::is_read_only(&path) {
+        return Err(format!("path {} is read-only", path));
+    }
+    if Path::new(&path).exists() {
+        return Err(format!("file {} already exists!", path));
+    }
+    check_space_reserve(context.config.space_reserve, &path, size)?;
+
+    // set_len is sparse where the filesystem supports it, and fails if the space isn't there
+    let allocated = fs::File::create(&path).and_then(|file| file.set_len(size));
+    if let Err(e) = allocated {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Could not allocate {} bytes for {}. Reason: {:?}", size, path, e));
+    }
+    Ok(size.to_string())
+}
+
+pub fn trim_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 3)?;
+    let path = args[0].clone();
+    let max_size = match args[1].parse::<u64>() {
+        Ok(size) => size,
+        Err(_) => return Err(format!("invalid maximum size: {}", args[1]))
+    };
+    // lines are kept whole unless explicitly disabled
+    let align_lines = args.get(2).map(|flag| parse_flag(Some(flag))).unwrap_or(true);
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    let _lock = context.lock_file(&path);
+
+    let size = match fs::metadata(&path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", path, e))
+    };
+    if size <= max_size {
+        return Ok("0".to_string());
+    }
+
+    // read one extra byte, to tell whether the kept part already starts on a new line
+    let start = size - max_size;
+    let mut tail = Vec::with_capacity(max_size as usize + 1);
+    let read = fs::File::open(&path).and_then(|mut file| {
+        std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(start - 1))?;
+        file.read_to_end(&mut tail)
+    });
+    if let Err(e) = read {
+        return Err(format!("While reading file, {}", e));
+    }
+    let kept = match align_lines {
+        true => {
+            let kept = match tail.iter().position(|byte| *byte == b'\n') {
+                Some(newline) => &tail[newline + 1..],
+                None => &tail[tail.len()..]
+            };
+            if std::str::from_utf8(kept).is_err() {
+                return Err(format!("file {} is not valid UTF-8, refusing to trim it by lines", path));
+            }
+            kept
+        },
+        false => &tail[1..]
+    };
+
+    write_atomic(&path, kept)?;
+    Ok((size - kept.len() as u64).to_string())
+}
+
+pub fn write_file_encoded(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let content = &args[1];
+    let encoding = get_encoding(&args[2])?;
+
+    let data = if encoding == encoding_rs::UTF_16LE {
+        content.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()
+    } else if encoding == encoding_rs::UTF_16BE {
+        content.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect()
+    } else if encoding.output_encoding() != encoding {
+        return Err(format!("writing {} is not supported", encoding.name()));
+    } else {
+        let (data, _, had_errors) = encoding.encode(content);
+        if had_errors {
+            // find the first character which could not be represented, to report it
+            let offending = content.chars()
+                .find(|c| encoding.encode(&c.to_string()).2)
+                .unwrap_or('?');
+            return Err(format!("content cannot be represented in {}: '{}' (U+{:04X})", encoding.name(), offending, offending as u32));
+        }
+        data.into_owned()
+    };
+
+    check_space_reserve(context.config.space_reserve, &path, data.len() as u64)?;
+    write_atomic(&path, &data)?;
+    Ok("The file was written successfully".to_string())
+}
+
+pub fn format_json(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let pretty = match args[1].to_lowercase().as_str() {
+        "pretty" => true,
+        "minify" => false,
+        other => return Err(format!("unknown json format: {} (expected pretty or minify)", other))
+    };
+    let _lock = context.lock_file(&path);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+    let value: serde_json::Value = match serde_json::from_str(&text) {
+        Ok(value) => value,
+        Err(e) => return Err(format!("file {} is not valid JSON (line {}, column {}): {}", path, e.line(), e.column(), e))
+    };
+
+    let formatted = match pretty {
+        true => serde_json::to_string_pretty(&value),
+        false => serde_json::to_string(&value)
+    };
+    let formatted = match formatted {
+        Ok(formatted) => formatted,
+        Err(e) => return Err(format!("Could not serialize json. Error: {}", e))
+    };
+    write_atomic(&path, formatted.as_bytes())?;
+    Ok("file was formatted successfully".to_string())
+}
+
+pub fn replace_in_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 3, 4)?;
+    let path = args[0].clone();
+    let find = &args[1];
+    let replace = &args[2];
+    let mode = args.get(3).map(|mode| mode.to_lowercase()).unwrap_or("all".to_string());
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    if find.is_empty() {
+        return Err("the text to find cannot be empty".to_string());
+    }
+    let _lock = context.lock_file(&path);
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return Err(format!("file {} is not valid UTF-8, refusing to modify it", path))
+    };
+
+    let (result, count) = match mode.as_str() {
+        "all" => (text.replace(find.as_str(), replace), text.matches(find.as_str()).count()),
+        "first" => match text.contains(find.as_str()) {
+            true => (text.replacen(find.as_str(), replace, 1), 1),
+            false => (text.clone(), 0)
+        },
+        "regex" => {
+            let regex = match regex::Regex::new(find) {
+                Ok(regex) => regex,
+                Err(e) => return Err(format!("invalid regex {}: {}", find, e))
+            };
+            let count = regex.find_iter(&text).count();
+            (regex.replace_all(&text, replace.as_str()).into_owned(), count)
+        },
+        _ => return Err(format!("unknown replace mode: {} (expected all, first or regex)", mode))
+    };
+
+    if count > 0 {
+        write_atomic(&path, result.as_bytes())?;
+    }
+    Ok(count.to_string())
+}
+
+pub fn get_md5(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let exists = Path::new(&path).exists();
+    if !exists {
+        return Err(format!("requested file ({}) does not exist!", path));
+    } else {
+        // read the file
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(format!("while reading file, {:?}", e));
+            }
+        };
+        // compute the md5 and return the value
+        let digest = md5::compute(data);
+        return Ok(format!("{:x}", digest));
+    }
+}
+
+pub fn block_hashes(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let block_size = match args[1].parse::<u64>() {
+        Ok(size) if size > 0 => size,
+        _ => return Err(format!("invalid block size: {}", args[1]))
+    };
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    let total = match file.metadata() {
+        Ok(md) => md.len(),
+        Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", path, e))
+    };
+    let count = total.div_ceil(block_size);
+    // report progress roughly every percent, rather than for every (possibly tiny) block
+    let progress_interval = (count / 100).max(1);
+
+    let mut hashes: Vec<String> = Vec::new();
+    let mut block = Vec::new();
+    for index in 0..count {
+        if index % progress_interval == 0 {
+            if context.is_cancelled() {
+                return Err("block_hashes was cancelled".to_string());
+            }
+            context.send_progress(Progress::new(
+                "Hashing".to_string(), 
+                format!("hashing block {} of {}", index + 1, count), 
+                (index as f64)/(count as f64)));
+        }
+        block.clear();
+        if let Err(e) = (&mut file).take(block_size).read_to_end(&mut block) {
+            return Err(format!("While reading file, {:?}", e));
+        }
+        hashes.push(format!("{:x}", md5::compute(&block)));
+    }
+    return match serde_json::to_string(&hashes) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json block hashes. Error: {}", e))
+    }
+}
+
+/// computes the hex digest of the given file with the given algorithm 
+/// (`md5` or `sha256`), reading it in blocks rather than all at once.
+fn hash_file(path: &str, algorithm: &str) -> Result<String, String> {
+    let mut hasher = StreamHasher::new(algorithm)?;
+    hasher.file = match fs::File::open(path) {
+        Ok(file) => Some(file),
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    hasher.catch_up(path)?;
+    Ok(hasher.finish())
+}
+
+/// incrementally hashes a file which may still be growing (such as a download 
+/// in progress), consuming whatever has been written since the last call.
+struct StreamHasher {
+    file: Option<fs::File>,
+    md5: Option<md5::Context>,
+    sha256: sha2::Sha256
+}
+
+impl StreamHasher {
+    fn new(algorithm: &str) -> Result<StreamHasher, String> {
+        let md5 = match algorithm.to_lowercase().as_str() {
+            "md5" => Some(md5::Context::new()),
+            "sha256" => None,
+            other => return Err(format!("unknown hash algorithm: {}", other))
+        };
+        Ok(StreamHasher { file: None, md5: md5, sha256: sha2::Sha256::new() })
+    }
+
+    /// hashes the data appended to the file since the last call. The file 
+    /// not existing yet is not an error, since it may not have been created.
+    fn catch_up(&mut self, path: &str) -> Result<(), String> {
+        if self.file.is_none() {
+            self.file = fs::File::open(path).ok();
+        }
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(())
+        };
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let count = match file.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(count) => count,
+                Err(e) => return Err(format!("while reading file, {:?}", e))
+            };
+            match self.md5.as_mut() {
+                Some(md5) => md5.consume(&buffer[..count]),
+                None => self.sha256.update(&buffer[..count])
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        return match self.md5 {
+            Some(md5) => format!("{:x}", md5.compute()),
+            None => format!("{:x}", self.sha256.finalize())
+        }
+    }
+}
+
+pub fn dir_hash(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let dir = args[0].clone();
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("path {} is not a directory!", dir));
+    }
+
+    // load the persisted cache the first time it is needed
+    let cache_file = context.config.hash_cache_file.clone();
+    let cache = context.state.hash_cache.get_or_insert_with(|| {
+        cache_file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    });
+    let mut changed = false;
+
+    // the combined hash covers each file's relative path and hash, in a stable order
+    let mut combined = md5::Context::new();
+    for entry in WalkDir::new(&dir).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Could not walk directory {}. Reason: {:?}", dir, e))
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let md = match entry.metadata() {
+            Ok(md) => md,
+            Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", entry.path().display(), e))
+        };
+        let modified = md.modified().ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or(0);
+        let path = entry.path().display().to_string();
+        let hash = match cache.get(&path) {
+            Some(cached) if cached.size == md.len() && cached.modified == modified => cached.md5.clone(),
+            _ => {
+                let md5 = hash_file(&path, "md5")?;
+                cache.insert(path.clone(), CachedHash { size: md.len(), modified: modified, md5: md5.clone() });
+                changed = true;
+                md5
+            }
+        };
+        let relative = entry.path().strip_prefix(&dir).unwrap_or(entry.path());
+        combined.consume(format!("{}:{}\n", relative.display(), hash).as_bytes());
+    }
+
+    if let Some(file) = cache_file {
+        if changed {
+            if let Ok(json) = serde_json::to_string(&*cache) {
+                let _ = write_atomic(&file, json.as_bytes());
+            }
+        }
+    }
+    Ok(format!("{:x}", combined.compute()))
+}
+
+/// how many of the largest files `dir_stats` returns by default
+const DEFAULT_LARGEST_FILES: usize = 10;
+
+pub fn dir_stats(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let dir = args[0].clone();
+    let largest_count = match args.get(1).map(|count| count.parse::<usize>()) {
+        None => DEFAULT_LARGEST_FILES,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => return Err(format!("invalid number of largest files: {}", args[1]))
+    };
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("path {} is not a directory!", dir));
+    }
+
+    // the total isn't known up front, so progress is measured by the top level entries walked
+    let top_level_count = fs::read_dir(&dir).map(|entries| entries.count()).unwrap_or(0).max(1);
+    let mut top_level_done = 0;
+    let mut stats = DirStats { path: dir.clone(), total_size: 0, file_count: 0, dir_count: 0, largest: Vec::new(), extensions: Vec::new(), skipped: 0 };
+    let mut largest: std::collections::BinaryHeap<std::cmp::Reverse<(u64, String)>> = std::collections::BinaryHeap::new();
+    let mut extensions: std::collections::HashMap<String, (u64, usize)> = std::collections::HashMap::new();
+
+    for entry in WalkDir::new(&dir).min_depth(1).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        if entry.depth() == 1 {
+            if context.is_cancelled() {
+                return Err("dir_stats was cancelled".to_string());
+            }
+            context.send_progress(Progress::new(
+                "Measuring".to_string(), 
+                entry.path().display().to_string(), 
+                (top_level_done as f64)/(top_level_count as f64)));
+            top_level_done += 1;
+        }
+        if entry.file_type().is_dir() {
+            stats.dir_count += 1;
+            continue;
+        }
+        let size = match entry.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        stats.total_size += size;
+        stats.file_count += 1;
+        let extension = entry.path().extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let totals = extensions.entry(extension).or_insert((0, 0));
+        totals.0 += size;
+        totals.1 += 1;
+        // keep only the largest files seen so far
+        largest.push(std::cmp::Reverse((size, entry.path().display().to_string())));
+        if largest.len() > largest_count {
+            largest.pop();
+        }
+    }
+
+    stats.largest = largest.into_sorted_vec().into_iter()
+        .map(|std::cmp::Reverse((size, path))| FileSize { path: path, size: size })
+        .collect();
+    stats.extensions = extensions.into_iter()
+        .map(|(extension, (size, count))| ExtensionStats { extension: extension, size: size, count: count })
+        .collect();
+    stats.extensions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.extension.cmp(&b.extension)));
+    return match serde_json::to_string(&stats) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json DirStats. Error: {}", e))
+    }
+}
+
+pub fn find_duplicates(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let dir = args[0].clone();
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("path {} is not a directory!", dir));
+    }
+
+    // only files which share their size with another file can be duplicates
+    let mut by_size: std::collections::BTreeMap<u64, Vec<String>> = std::collections::BTreeMap::new();
+    for entry in WalkDir::new(&dir).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if let Ok(md) = entry.metadata() {
+            by_size.entry(md.len()).or_default().push(entry.path().display().to_string());
+        }
+    }
+    // empty files are skipped too, since removing them reclaims nothing
+    by_size.retain(|size, paths| *size > 0 && paths.len() > 1);
+    let count: usize = by_size.values().map(|paths| paths.len()).sum();
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut hashed = 0;
+    // largest first, since those reclaim the most space
+    for (size, paths) in by_size.into_iter().rev() {
+        let mut by_hash: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();
+        for path in paths {
+            if context.is_cancelled() {
+                return Err("find_duplicates was cancelled".to_string());
+            }
+            context.send_progress(Progress::new(
+                "Finding duplicates".to_string(), 
+                format!("hashing file {} of {}", hashed + 1, count), 
+                (hashed as f64)/(count as f64)));
+            hashed += 1;
+            by_hash.entry(hash_file(&path, "md5")?).or_default().push(path);
+        }
+        for (md5, paths) in by_hash {
+            if paths.len() > 1 {
+                groups.push(DuplicateGroup { md5: md5, size: size, paths: paths });
+            }
+        }
+    }
+    return match serde_json::to_string(&groups) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json duplicate groups. Error: {}", e))
+    }
+}
+
+/// lists the relative path of every file under the given directory, in a stable order
+fn relative_file_paths(dir: &str) -> Vec<String> {
+    WalkDir::new(dir).sort_by_file_name().into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(dir).ok().map(|relative| relative.to_string_lossy().replace('\\', "/")))
+        .collect()
+}
+
+/// writes the manifest of the given files to `writer`, one entry at a time
+fn write_manifest(context: &MessageContext, writer: &mut dyn Write, dir: &str, paths: &[String]) -> Result<(), String> {
+    let count = paths.len();
+    writer.write_all(b"{\"files\":[").map_err(|e| format!("{:?}", e))?;
+    for (index, relative) in paths.iter().enumerate() {
+        context.send_progress(Progress::new(
+            "Exporting manifest".to_string(), 
+            relative.clone(), 
+            (index as f64)/(count as f64)));
+        let full_path = format!("{}/{}", dir.trim_end_matches('/'), relative);
+        let size = fs::metadata(&full_path).map(|md| md.len()).map_err(|e| format!("{:?}", e))?;
+        let entry = ManifestEntry { path: relative.clone(), size: size, md5: hash_file(&full_path, "md5")? };
+        if index > 0 {
+            writer.write_all(b",").map_err(|e| format!("{:?}", e))?;
+        }
+        serde_json::to_writer(&mut *writer, &entry).map_err(|e| format!("{:?}", e))?;
+    }
+    writer.write_all(b"]}").map_err(|e| format!("{:?}", e))?;
+    writer.flush().map_err(|e| format!("{:?}", e))
+}
+
+pub fn export_manifest(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let dir = args[0].clone();
+    let manifest_path = args[1].clone();
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("path {} is not a directory!", dir));
+    }
+
+    // entries are written as they are hashed, rather than collected first
+    let tmp_path = format!("{}.tmp", manifest_path);
+    let file = match fs::File::create(&tmp_path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not create manifest {}. Reason: {:?}", manifest_path, e))
+    };
+    let mut writer = std::io::BufWriter::new(file);
+    // the manifest may be written into the directory itself, but shouldn't list itself
+    let paths: Vec<String> = relative_file_paths(&dir).into_iter()
+        .filter(|relative| {
+            let full_path = Path::new(&dir).join(relative);
+            full_path != Path::new(&manifest_path) && full_path != Path::new(&tmp_path)
+        })
+        .collect();
+    let result = write_manifest(context, &mut writer, &dir, &paths);
+    drop(writer);
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Could not write manifest {}. Reason: {}", manifest_path, e));
+    }
+    let _ = fs::remove_file(&manifest_path);
+    if let Err(e) = fs::rename(&tmp_path, &manifest_path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Could not write manifest {}. Reason: {:?}", manifest_path, e));
+    }
+    Ok(paths.len().to_string())
+}
+
+/// resolves a manifest entry's path within the given directory, rejecting 
+/// entries which would escape the directory or the sandbox
+fn manifest_entry_path(config: &EngineConfig, dir: &str, entry: &str) -> Result<String, String> {
+    let dir = crate::sandbox::normalize_path(dir)?;
+    let full_path = crate::sandbox::normalize_path(&format!("{}/{}", dir.trim_end_matches('/'), entry))?;
+    if !full_path.starts_with(&format!("{}/", dir.trim_end_matches('/'))) {
+        return Err(format!("manifest entry {} is outside of {}", entry, dir));
+    }
+    crate::sandbox::check_path(config, &full_path)
+}
+
+pub fn verify_manifest(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let dir = args[0].clone();
+    let manifest_path = args[1].clone();
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("path {} is not a directory!", dir));
+    }
+    let manifest: Manifest = match fs::File::open(&manifest_path) {
+        Ok(file) => match serde_json::from_reader(std::io::BufReader::new(file)) {
+            Ok(manifest) => manifest,
+            Err(e) => return Err(format!("Could not parse manifest {}. Error: {}", manifest_path, e))
+        },
+        Err(e) => return Err(format!("Could not open manifest {}. Reason: {:?}", manifest_path, e))
+    };
+
+    let present = relative_file_paths(&dir);
+    let mut diff = ManifestDiff { added: Vec::new(), removed: Vec::new(), modified: Vec::new() };
+    let expected: std::collections::HashMap<&str, &ManifestEntry> = manifest.files.iter().map(|entry| (entry.path.as_str(), entry)).collect();
+    for relative in &present {
+        if !expected.contains_key(relative.as_str()) {
+            diff.added.push(relative.clone());
+        }
+    }
+
+    let count = manifest.files.len();
+    for (index, entry) in manifest.files.iter().enumerate() {
+        context.send_progress(Progress::new(
+            "Verifying manifest".to_string(), 
+            entry.path.clone(), 
+            (index as f64)/(count as f64)));
+        let full_path = manifest_entry_path(context.config, &dir, &entry.path)?;
+        let size = match fs::metadata(&full_path) {
+            Ok(md) if md.is_file() => md.len(),
+            _ => {
+                diff.removed.push(entry.path.clone());
+                continue;
+            }
+        };
+        // only files of the right size need to be hashed
+        if size != entry.size || hash_file(&full_path, "md5")? != entry.md5 {
+            diff.modified.push(entry.path.clone());
+        }
+    }
+
+    return match serde_json::to_string(&diff) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json ManifestDiff. Error: {}", e))
+    }
+}
+
+/// the largest file which will be diffed, to keep memory use reasonable
+const MAX_DIFF_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// reads a file as text for diffing, refusing large or binary files
+fn read_diffable(path: &str) -> Result<String, String> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Err(format!("requested file ({}) does not exist!", path))
+    };
+    if metadata.len() > MAX_DIFF_FILE_SIZE {
+        return Err(format!("file {} is too large to diff ({} bytes, max {})", path, metadata.len(), MAX_DIFF_FILE_SIZE));
+    }
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading file, {}", e))
+    };
+    return match String::from_utf8(data) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(format!("file {} is not valid UTF-8, cannot diff binary files", path))
+    }
+}
+
+pub fn diff_files(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let old_text = read_diffable(&args[0])?;
+    let new_text = read_diffable(&args[1])?;
+
+    let diff = similar::TextDiff::from_lines(&old_text, &new_text);
+    let lines: Vec<DiffLine> = diff.iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                similar::ChangeTag::Insert => "added",
+                similar::ChangeTag::Delete => "removed",
+                similar::ChangeTag::Equal => "unchanged"
+            }.to_string(),
+            line: change.value().trim_end_matches(['\r', '\n']).to_string()
+        })
+        .collect();
+
+    return match serde_json::to_string(&lines) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json diff. Error: {}", e))
+    }
+}
+
+pub fn apply_patch(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let _lock = context.lock_file(&path);
+    let original = read_diffable(&path)?;
+    let patch = match diffy::Patch::from_str(&args[1]) {
+        Ok(patch) => patch,
+        Err(e) => return Err(format!("Could not parse patch. Error: {}", e))
+    };
+
+    // the original is only replaced once the whole patch applied cleanly
+    let patched = match diffy::apply(&original, &patch) {
+        Ok(patched) => patched,
+        Err(e) => return Err(format!("Patch does not apply cleanly to {}: {}", path, e))
+    };
+    write_atomic(&path, patched.as_bytes())?;
+    Ok(format!("applied {} hunks", patch.hunks().len()))
+}
+
+pub fn unzip(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let filepath = args[0].clone();
+    let destination = args[1].clone();
+
+    if !Path::new(&filepath).exists() {
+        return Err(format!("file {} does not exist!", filepath));
+    }
+    if !Path::new(&filepath).is_file() {
+        return Err(format!("path {} is not a file!", filepath));
+    }
+
+    if !Path::new(&destination).exists() {
+        return Err(format!("path {} does not exist!", destination));
+    }
+    if !Path::new(&destination).is_dir() {
+        return Err(format!("path {} is not a directory!", destination));
+    }
+
+    if context.config.space_reserve.is_some() {
+        let (_, required) = zip_install_size(&filepath)?;
+        check_space_reserve(context.config.space_reserve, &destination, required)?;
+    }
+
+    let progress = |name: &str, progress: f64| {
+        if context.is_cancelled() {
+            return Err("unzip was cancelled".to_string());
+        }
+        context.send_progress(Progress::new(
+            "Extracting".to_string(), 
+            name.to_string(), 
+            progress));
+        Ok(())
+    };
+    extract_zip(&filepath, &destination, context.config, &progress, &mut Vec::new())?;
+    Ok("unzip succeeded".to_string())
+}
+
+/// extracts every file of the given zip into the given directory. Progress is reported
+/// before each file, and extraction stops if the callback returns an `Err`.
+/// The path of every file which did not exist before is added to `created`. 
+/// Nothing is extracted if any entry would land outside of the destination or the sandbox.
+fn extract_zip(filepath: &str, destination: &str, config: &EngineConfig, progress: &dyn Fn(&str, f64) -> Result<(), String>, created: &mut Vec<std::path::PathBuf>) -> Result<(), String> {
+    let mut zip = match unzipper::get_zip_archive(filepath) {
+        Ok(zip) => zip,
+        Err(_) => return Err("Could not parse zip file!".to_string())
+    };
+
+    let count = zip.len();
+
+    // resolve every entry's path up front, so an archive with an escaping entry is rejected entirely
+    let mut paths = Vec::with_capacity(count);
+    for file_no in 0..count {
+        let file = match zip.by_index_raw(file_no) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Could not read zip entry {}. Reason: {:?}", file_no, e))
+        };
+        // absolute names, or names with `..`, have no enclosed name
+        let path = match file.enclosed_name() {
+            Some(name) => Path::new(destination).join(name),
+            None => return Err(format!("zip entry {} would extract outside of {}", file.name(), destination))
+        };
+        crate::sandbox::check_path(config, &path.display().to_string())?;
+        paths.push(path);
+    }
+
+    for (file_no, path) in paths.into_iter().enumerate() {
+        let mut file = match zip.by_index(file_no) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Could not read zip entry {}. Reason: {:?}", file_no, e))
+        };
+        if !file.is_file() {
+            continue;
+        }
+
+        progress(file.name(), (file_no as f64)/(count as f64))?;
+        
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        if !path.exists() {
+            created.push(path.clone());
+        }
+        let mut file_data = vec![];
+        if let Err(e) = file.read_to_end(&mut file_data) {
+            return Err(format!("Could not extract {}. Reason: {:?}", file.name(), e));
+        }
+        if let Err(e) = std::fs::write(&path, file_data) {
+            return Err(format!("Could not write {}. Reason: {:?}", path.display(), e));
+        }
+    }
+    Ok(())
+}
+
+/// the allocation unit assumed when estimating space on the SD card, since every 
+/// file occupies whole clusters (32 KiB is typical for SD cards formatted as FAT32)
+const ASSUMED_CLUSTER_SIZE: u64 = 32 * 1024;
+
+/// returns the number of files in the given zip, and the space needed to extract
+/// them, in bytes, with each file rounded up to whole clusters.
+fn zip_install_size(filepath: &str) -> Result<(usize, u64), String> {
+    let mut zip = match unzipper::get_zip_archive(filepath) {
+        Ok(zip) => zip,
+        Err(_) => return Err("Could not parse zip file!".to_string())
+    };
+    let mut file_count = 0;
+    let mut required = 0;
+    for index in 0..zip.len() {
+        let file = match zip.by_index(index) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Could not read zip entry {}. Reason: {:?}", index, e))
+        };
+        if !file.is_file() {
+            continue;
+        }
+        file_count += 1;
+        // round each file up to whole clusters
+        required += file.size().div_ceil(ASSUMED_CLUSTER_SIZE).max(1) * ASSUMED_CLUSTER_SIZE;
+    }
+    Ok((file_count, required))
+}
+
+pub fn can_install(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let filepath = args[0].clone();
+    let destination = args[1].clone();
+    if filepath.starts_with("http://") || filepath.starts_with("https://") {
+        // Curler exposes neither HEAD requests nor response headers, so a remote
+        // archive's Content-Length can't be read without downloading it
+        return Err(format!("can_install only supports local zip files, download {} first", filepath));
+    }
+    if !Path::new(&filepath).is_file() {
+        return Err(format!("file {} does not exist!", filepath));
+    }
+
+    let (file_count, required) = zip_install_size(&filepath)?;
+    let free = platform::free_space(&destination);
+    let headroom = free.map(|free| free as i64 - required as i64);
+    let check = InstallCheck { 
+        file_count: file_count, 
+        required: required, 
+        free: free, 
+        headroom: headroom, 
+        fits: headroom.map(|headroom| headroom >= 0).unwrap_or(true) 
+    };
+    return match serde_json::to_string(&check) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json InstallCheck. Error: {}", e))
+    }
+}
+
+/// the default limits of `check_zip_safety`
+const DEFAULT_ZIP_MAX_TOTAL_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+const DEFAULT_ZIP_MAX_RATIO: f64 = 100.0;
+const DEFAULT_ZIP_MAX_ENTRIES: usize = 50000;
+
+/// entries smaller than this are not ratio-checked, since small text files
+/// legitimately compress very well and can't do much harm anyway
+const ZIP_RATIO_MIN_SIZE: u64 = 1024 * 1024;
+
+pub fn check_zip_safety(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 4)?;
+    let filepath = args[0].clone();
+    let max_total_size = match args.get(1).map(|size| size.parse::<u64>()) {
+        None => DEFAULT_ZIP_MAX_TOTAL_SIZE,
+        Some(Ok(size)) => size,
+        Some(Err(_)) => return Err(format!("invalid maximum total size: {}", args[1]))
+    };
+    let max_ratio = match args.get(2).map(|ratio| ratio.parse::<f64>()) {
+        None => DEFAULT_ZIP_MAX_RATIO,
+        Some(Ok(ratio)) if ratio > 0.0 => ratio,
+        Some(_) => return Err(format!("invalid maximum compression ratio: {}", args[2]))
+    };
+    let max_entries = match args.get(3).map(|count| count.parse::<usize>()) {
+        None => DEFAULT_ZIP_MAX_ENTRIES,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => return Err(format!("invalid maximum entry count: {}", args[3]))
+    };
+    if !Path::new(&filepath).is_file() {
+        return Err(format!("file {} does not exist!", filepath));
+    }
+
+    let mut zip = match unzipper::get_zip_archive(&filepath) {
+        Ok(zip) => zip,
+        Err(_) => return Err("Could not parse zip file!".to_string())
+    };
+    let mut report = ZipSafetyReport { safe: true, entry_count: zip.len(), total_size: 0, max_ratio: 0.0, unsafe_paths: Vec::new(), problems: Vec::new() };
+    for index in 0..zip.len() {
+        // only the central directory is read, so no entry is decompressed here
+        let entry = match zip.by_index_raw(index) {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Could not read zip entry {}. Reason: {:?}", index, e))
+        };
+        // absolute names, or names with `..`, would extract outside of the destination
+        if entry.enclosed_name().is_none() {
+            report.unsafe_paths.push(entry.name().to_string());
+        }
+        report.total_size = report.total_size.saturating_add(entry.size());
+        if entry.size() >= ZIP_RATIO_MIN_SIZE {
+            let ratio = entry.size() as f64 / (entry.compressed_size().max(1) as f64);
+            report.max_ratio = report.max_ratio.max(ratio);
+        }
+    }
+
+    if report.entry_count > max_entries {
+        report.problems.push(format!("the archive has {} entries, more than the limit of {}", report.entry_count, max_entries));
+    }
+    if report.total_size > max_total_size {
+        report.problems.push(format!("the archive extracts to {} bytes, more than the limit of {}", report.total_size, max_total_size));
+    }
+    if report.max_ratio > max_ratio {
+        report.problems.push(format!("an entry is compressed {:.0} times, more than the limit of {}", report.max_ratio, max_ratio));
+    }
+    if !report.unsafe_paths.is_empty() {
+        report.problems.push(format!("{} entries would extract outside of the destination", report.unsafe_paths.len()));
+    }
+    report.safe = report.problems.is_empty();
+    return match serde_json::to_string(&report) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json ZipSafetyReport. Error: {}", e))
+    }
+}
+
+pub fn read_zip_entry(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 3)?;
+    let filepath = args[0].clone();
+    let entry_name = args[1].clone();
+    let as_base64 = parse_flag(args.get(2));
+    if !Path::new(&filepath).is_file() {
+        return Err(format!("file {} does not exist!", filepath));
+    }
+
+    let mut zip = match unzipper::get_zip_archive(&filepath) {
+        Ok(zip) => zip,
+        Err(_) => return Err("Could not parse zip file!".to_string())
+    };
+    let mut entry = match zip.by_name(&entry_name) {
+        Ok(entry) => entry,
+        Err(_) => return Err(format!("entry {} does not exist in {}", entry_name, filepath))
+    };
+    let mut data = Vec::new();
+    if let Err(e) = entry.read_to_end(&mut data) {
+        return Err(format!("Could not read entry {}. Reason: {:?}", entry_name, e));
+    }
+
+    if as_base64 {
+        return Ok(base64::engine::general_purpose::STANDARD.encode(data));
+    }
+    return match String::from_utf8(data) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(format!("entry {} is not valid UTF-8; it must be read as base64 instead", entry_name))
+    }
+}
+
+/// the most (uncompressed) content which `read_zip_entries` returns, to keep responses reasonable
+const MAX_ZIP_ENTRIES_SIZE: u64 = 8 * 1024 * 1024;
+
+pub fn read_zip_entries(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let filepath = args[0].clone();
+    let pattern = match glob::Pattern::new(&args[1]) {
+        Ok(pattern) => pattern,
+        Err(e) => return Err(format!("invalid glob pattern {}: {}", args[1], e))
+    };
+    if !Path::new(&filepath).is_file() {
+        return Err(format!("file {} does not exist!", filepath));
+    }
+
+    let mut zip = match unzipper::get_zip_archive(&filepath) {
+        Ok(zip) => zip,
+        Err(_) => return Err("Could not parse zip file!".to_string())
+    };
+    let mut entries: std::collections::BTreeMap<String, String> = std::collections::BTreeMap::new();
+    let mut total = 0;
+    for index in 0..zip.len() {
+        let mut entry = match zip.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Could not read zip entry {}. Reason: {:?}", index, e))
+        };
+        if !entry.is_file() || !pattern.matches(entry.name()) {
+            continue;
+        }
+        total += entry.size();
+        if total > MAX_ZIP_ENTRIES_SIZE {
+            return Err(format!("the entries matching {} are larger than {} bytes; use a narrower pattern", args[1], MAX_ZIP_ENTRIES_SIZE));
+        }
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            return Err(format!("Could not read entry {}. Reason: {:?}", entry.name(), e));
+        }
+        entries.insert(entry.name().to_string(), base64::engine::general_purpose::STANDARD.encode(data));
+    }
+    return match serde_json::to_string(&entries) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json zip entries. Error: {}", e))
+    }
+}
+
+pub fn compress_file(context: &mut MessageContext) -> Result<String, String> {
+    transcode_file(context, true)
+}
+
+pub fn decompress_file(context: &mut MessageContext) -> Result<String, String> {
+    transcode_file(context, false)
+}
+
+/// compresses (or decompresses) the input file (first argument) into the output file 
+/// (second argument) with the given algorithm (third argument), reporting progress
+/// as the input is read. The output is removed again if anything fails.
+fn transcode_file(context: &MessageContext, compress: bool) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let input = args[0].clone();
+    let output = args[1].clone();
+    let algorithm = args[2].to_lowercase();
+    if algorithm != "gzip" && algorithm != "zstd" {
+        return Err(format!("unsupported compression algorithm: {} (expected gzip or zstd)", algorithm));
+    }
+    let input_size = match fs::metadata(&input) {
+        Ok(md) if md.is_file() => md.len(),
+        _ => return Err(format!("file {} does not exist!", input))
+    };
+    let estimate = if compress { input_size / 2 } else { input_size.saturating_mul(2) };
+    check_space_reserve(context.config.space_reserve, &output, estimate)?;
+    let reader = match fs::File::open(&input) {
+        Ok(file) => ProgressReader { inner: file, read: 0, total: input_size, context: context, compress: compress },
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", input, e))
+    };
+    let writer = match fs::File::create(&output) {
+        Ok(file) => std::io::BufWriter::new(file),
+        Err(e) => return Err(format!("Could not create file {}. Reason: {:?}", output, e))
+    };
+
+    let result = match (algorithm.as_str(), compress) {
+        ("gzip", true) => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            std::io::copy(&mut std::io::BufReader::new(reader), &mut encoder)
+                .and_then(|_| encoder.finish())
+                .and_then(|mut writer| writer.flush())
+        },
+        ("gzip", false) => {
+            let mut writer = writer;
+            std::io::copy(&mut flate2::read::GzDecoder::new(std::io::BufReader::new(reader)), &mut writer)
+                .and_then(|_| writer.flush())
+        },
+        (_, true) => zstd_compress(std::io::BufReader::new(reader), writer),
+        (_, false) => zstd_decompress(std::io::BufReader::new(reader), writer)
+    };
+    if let Err(e) = result {
+        let _ = fs::remove_file(&output);
+        return match context.is_cancelled() {
+            true => Err(format!("{} was cancelled", if compress { "compression" } else { "decompression" })),
+            false => Err(format!("Could not {} file {}. Reason: {:?}", if compress { "compress" } else { "decompress" }, input, e))
+        };
+    }
+
+    let output_size = fs::metadata(&output).map(|md| md.len()).unwrap_or(0);
+    let (compressed, uncompressed) = if compress { (output_size, input_size) } else { (input_size, output_size) };
+    let transcoded = CompressionResult { 
+        input_size: input_size, 
+        output_size: output_size, 
+        ratio: compressed as f64 / (uncompressed.max(1) as f64) 
+    };
+    return match serde_json::to_string(&transcoded) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json CompressionResult. Error: {}", e))
+    }
+}
+
+/// how much of the input `zstd_compress` puts into each zstd frame
+const ZSTD_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// compresses everything read into consecutive zstd frames. ruzstd's encoder panics 
+/// on io errors (which would abort the game), so it is only ever given in-memory 
+/// buffers, one frame at a time.
+fn zstd_compress<R: Read, W: Write>(mut reader: R, mut writer: W) -> std::io::Result<()> {
+    let mut chunk = Vec::with_capacity(ZSTD_FRAME_SIZE);
+    loop {
+        chunk.clear();
+        (&mut reader).take(ZSTD_FRAME_SIZE as u64).read_to_end(&mut chunk)?;
+        let frame = ruzstd::encoding::compress_to_vec(chunk.as_slice(), ruzstd::encoding::CompressionLevel::Fastest);
+        writer.write_all(&frame)?;
+        if chunk.len() < ZSTD_FRAME_SIZE {
+            break;
+        }
+    }
+    writer.flush()
+}
+
+/// decompresses every zstd frame read, so that files written by `zstd_compress`
+/// (or any other multi-frame encoder) are restored completely
+fn zstd_decompress<R: std::io::BufRead, W: Write>(mut reader: R, mut writer: W) -> std::io::Result<()> {
+    loop {
+        let mut decoder = ruzstd::decoding::StreamingDecoder::new(&mut reader)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}", e)))?;
+        std::io::copy(&mut decoder, &mut writer)?;
+        if reader.fill_buf()?.is_empty() {
+            break;
+        }
+    }
+    writer.flush()
+}
+
+/// how often `ProgressReader` reports progress, in bytes read
+const PROGRESS_INTERVAL_BYTES: u64 = 1024 * 1024;
+
+/// reads a file while reporting progress, failing the read once the request is cancelled
+struct ProgressReader<'a, 'b> {
+    inner: fs::File,
+    read: u64,
+    total: u64,
+    context: &'a MessageContext<'b>,
+    compress: bool
+}
+
+impl Read for ProgressReader<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        let previous = self.read;
+        self.read += count as u64;
+        // only report at each mebibyte, rather than for every (small) read
+        if previous / PROGRESS_INTERVAL_BYTES == self.read / PROGRESS_INTERVAL_BYTES {
+            return Ok(count);
+        }
+        if self.context.is_cancelled() {
+            return Err(std::io::Error::other("cancelled"));
+        }
+        self.context.send_progress(Progress::new(
+            if self.compress { "Compressing" } else { "Decompressing" }.to_string(), 
+            format!("{} of {} bytes", self.read, self.total), 
+            (self.read as f64)/(self.total.max(1) as f64)));
+        Ok(count)
+    }
+}
+
+pub fn merge_dir(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 3, 4)?;
+    let source = args[0].clone();
+    let destination = args[1].clone();
+    let policy = args[2].to_lowercase();
+    let preserve_timestamps = parse_flag(args.get(3));
+    if !["overwrite", "skip", "newer"].contains(&policy.as_str()) {
+        return Err(format!("unknown conflict policy: {} (expected overwrite, skip or newer)", policy));
+    }
+    if !Path::new(&source).is_dir() {
+        return Err(format!("path {} is not a directory!", source));
+    }
+
+    let files: Vec<walkdir::DirEntry> = WalkDir::new(&source).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .collect();
+    // assume everything is copied, since that is the most space the merge could need
+    let total: u64 = files.iter().filter_map(|file| file.metadata().ok()).map(|md| md.len()).sum();
+    check_space_reserve(context.config.space_reserve, &destination, total)?;
+    let count = files.len();
+    let mut result = MergeResult { copied: 0, skipped: 0, overwritten: 0 };
+
+    for (index, file) in files.iter().enumerate() {
+        if context.is_cancelled() {
+            return Err("merge was cancelled".to_string());
+        }
+        let relative = file.path().strip_prefix(&source).unwrap();
+        context.send_progress(Progress::new(
+            "Merging".to_string(), 
+            relative.display().to_string(), 
+            (index as f64)/(count as f64)));
+
+        let target = Path::new(&destination).join(relative);
+        let exists = target.exists();
+        if exists {
+            let replace = match policy.as_str() {
+                "overwrite" => true,
+                "skip" => false,
+                _ => {
+                    let source_time = file.metadata().ok().and_then(|md| md.modified().ok());
+                    let target_time = fs::metadata(&target).ok().and_then(|md| md.modified().ok());
+                    match (source_time, target_time) {
+                        (Some(source_time), Some(target_time)) => source_time > target_time,
+                        // without timestamps, we cannot tell which is newer
+                        _ => false
+                    }
+                }
+            };
+            if !replace {
+                result.skipped += 1;
+                continue;
+            }
+        }
+
+        if let Some(parent) = target.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return Err(format!("Could not create directory {}. Reason: {:?}", parent.display(), e));
+            }
+        }
+        if let Err(e) = copy_file(file.path(), &target, preserve_timestamps) {
+            return Err(format!("Could not copy {}. Reason: {:?}", file.path().display(), e));
+        }
+        match exists {
+            true => result.overwritten += 1,
+            false => result.copied += 1
+        }
+    }
+
+    return match serde_json::to_string(&result) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json MergeResult. Error: {}", e))
+    }
+}
+
+/// whether renaming `from` to `to` only changes the casing of the name
+fn is_case_only_rename(from: &Path, to: &Path) -> bool {
+    let from = from.to_string_lossy();
+    let to = to.to_string_lossy();
+    from != to && from.to_lowercase() == to.to_lowercase()
+}
+
+/// renames `from` to `to`. Case-insensitive filesystems may refuse, or silently ignore,
+/// a rename which only changes the casing of the name, so such renames are done in two 
+/// steps via an intermediate name. Returns whether the two-step rename was used.
+fn rename_path(from: &Path, to: &Path) -> std::io::Result<bool> {
+    if !is_case_only_rename(from, to) {
+        fs::rename(from, to)?;
+        return Ok(false);
+    }
+    let mut intermediate = to.with_file_name(format!(".rename_{}.tmp", unix_timestamp()));
+    let mut counter = 1;
+    while intermediate.exists() {
+        intermediate = to.with_file_name(format!(".rename_{}_{}.tmp", unix_timestamp(), counter));
+        counter += 1;
+    }
+    fs::rename(from, &intermediate)?;
+    if let Err(e) = fs::rename(&intermediate, to) {
+        // put the original back, rather than leaving it under the intermediate name
+        let _ = fs::rename(&intermediate, from);
+        return Err(e);
+    }
+    Ok(true)
+}
+
+pub fn rename(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let from = Path::new(&args[0]);
+    let to = Path::new(&args[1]);
+    if !from.exists() {
+        return Err(format!("path {} does not exist!", from.display()));
+    }
+    // on a case-insensitive filesystem, the destination of a case-only rename 
+    // "exists" already, since it is the source itself
+    if to.exists() && !is_case_only_rename(from, to) {
+        return Err(format!("path {} already exists!", to.display()));
+    }
+
+    return match rename_path(from, to) {
+        Ok(true) => Ok("renamed via an intermediate name (case-only rename)".to_string()),
+        Ok(false) => Ok("renamed successfully".to_string()),
+        Err(e) => Err(format!("Could not rename {}. Reason: {:?}", from.display(), e))
+    }
+}
+
+pub fn split_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let part_size = match args[1].parse::<u64>() {
+        Ok(size) if size > 0 => size,
+        _ => return Err(format!("invalid part size: {}", args[1]))
+    };
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    let total = match file.metadata() {
+        Ok(md) => md.len(),
+        Err(e) => return Err(format!("Could not read metadata of {}. Reason: {:?}", path, e))
+    };
+    check_space_reserve(context.config.space_reserve, &path, total)?;
+    let count = total.div_ceil(part_size).max(1);
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut digest = md5::Context::new();
+    let mut result = Ok(());
+    for index in 0..count {
+        context.send_progress(Progress::new(
+            "Splitting".to_string(), 
+            format!("writing part {} of {}", index + 1, count), 
+            (index as f64)/(count as f64)));
+        let part_path = format!("{}.{:03}", path, index + 1);
+        parts.push(part_path.clone());
+        let part_file = match fs::File::create(&part_path) {
+            Ok(part_file) => part_file,
+            Err(e) => {
+                result = Err(format!("Could not create part {}. Reason: {:?}", part_path, e));
+                break;
+            }
+        };
+        let mut writer = Md5Writer { inner: std::io::BufWriter::new(part_file), digest: &mut digest };
+        if let Err(e) = std::io::copy(&mut (&mut file).take(part_size), &mut writer).and_then(|_| writer.flush()) {
+            result = Err(format!("Could not write part {}. Reason: {:?}", part_path, e));
+            break;
+        }
+    }
+    // don't leave an incomplete set of parts behind
+    if let Err(e) = result {
+        for part in parts {
+            let _ = fs::remove_file(part);
+        }
+        return Err(e);
+    }
+
+    let split = SplitResult { parts: parts, md5: format!("{:x}", digest.compute()) };
+    return match serde_json::to_string(&split) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json SplitResult. Error: {}", e))
+    }
+}
+
+/// forwards everything written to the inner writer, feeding it
+/// to an md5 digest on the way, so that files can be hashed as they are copied
+struct Md5Writer<'a, W: Write> {
+    inner: W,
+    digest: &'a mut md5::Context
+}
+
+impl<'a, W: Write> Write for Md5Writer<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.digest.consume(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// parses a JSON array of paths, checking each against the sandbox
+/// and returning their normalized forms
+fn sandboxed_paths(config: &EngineConfig, json: &str) -> Result<Vec<String>, String> {
+    let paths: Vec<String> = match serde_json::from_str(json) {
+        Ok(paths) => paths,
+        Err(e) => return Err(format!("expected a JSON array of paths. Error: {}", e))
+    };
+    return paths.iter().map(|path| crate::sandbox::check_path(config, path)).collect();
+}
+
+pub fn join_files(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 3)?;
+    let output = args[0].clone();
+    // the parts are inside the json, so the sandbox is checked here
+    let parts = sandboxed_paths(context.config, &args[1])?;
+    let expected_md5 = args.get(2).map(|md5| md5.trim().to_lowercase());
+    if Path::new(&output).exists() {
+        return Err(format!("path {} already exists!", output));
+    }
+    let total: u64 = parts.iter().filter_map(|part| fs::metadata(part).ok()).map(|md| md.len()).sum();
+    check_space_reserve(context.config.space_reserve, &output, total)?;
+    let mut out = match fs::File::create(&output) {
+        Ok(file) => std::io::BufWriter::new(file),
+        Err(e) => return Err(format!("Could not create file {}. Reason: {:?}", output, e))
+    };
+
+    let count = parts.len();
+    let mut digest = md5::Context::new();
+    for (index, part) in parts.iter().enumerate() {
+        context.send_progress(Progress::new(
+            "Joining".to_string(), 
+            format!("reading part {} of {}", index + 1, count), 
+            (index as f64)/(count as f64)));
+        let mut part_file = match fs::File::open(part) {
+            Ok(part_file) => part_file,
+            Err(e) => {
+                drop(out);
+                let _ = fs::remove_file(&output);
+                return Err(format!("Could not read part {}. Reason: {:?}", part, e));
+            }
+        };
+        let mut writer = Md5Writer { inner: &mut out, digest: &mut digest };
+        if let Err(e) = std::io::copy(&mut part_file, &mut writer) {
+            drop(out);
+            let _ = fs::remove_file(&output);
+            return Err(format!("Could not join part {} into {}. Reason: {:?}", part, output, e));
+        }
+    }
+    if let Err(e) = out.flush() {
+        drop(out);
+        let _ = fs::remove_file(&output);
+        return Err(format!("Could not write file {}. Reason: {:?}", output, e));
+    }
+    drop(out);
+
+    // verify the result against the md5 given by split_file, if any
+    let md5 = format!("{:x}", digest.compute());
+    if let Some(expected) = expected_md5 {
+        if md5 != expected {
+            let _ = fs::remove_file(&output);
+            return Err(format!("joined file has md5 {}, but expected {}", md5, expected));
+        }
+    }
+    Ok(md5)
+}
+
+/// the most directory entries a FAT32 directory can hold
+const FAT32_MAX_DIR_ENTRIES: usize = 65536;
+
+/// the number of FAT32 directory entries used by a file with the given name: one
+/// for the short name, plus one per 13 characters of the long name.
+fn fat32_entries_for(name_length: usize) -> usize {
+    1 + name_length.div_ceil(13)
+}
+
+pub fn can_create_files(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 3)?;
+    let dir = args[0].clone();
+    let count = match args[1].parse::<usize>() {
+        Ok(count) => count,
+        Err(_) => return Err(format!("invalid file count: {}", args[1]))
+    };
+    let name_length = match args.get(2).map(|length| length.parse::<usize>()) {
+        None => 32,
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Err(format!("invalid name length: {}", args[2]))
+    };
+
+    // the limits which apply depend on the filesystem, which we can't query, 
+    // so assume the strictest likely one (FAT32's directory entry limit)
+    let feasibility = match fs::read_dir(&dir) {
+        Ok(entries) => {
+            // every directory but the root also holds `.` and `..`
+            let mut used = 2;
+            for entry in entries.flatten() {
+                used += fat32_entries_for(entry.file_name().to_string_lossy().chars().count());
+            }
+            let needed = count * fat32_entries_for(name_length);
+            let free = FAT32_MAX_DIR_ENTRIES.saturating_sub(used);
+            match needed <= free {
+                true => Feasibility { possible: true, reason: format!("about {} of {} directory entries are free", free, FAT32_MAX_DIR_ENTRIES) },
+                false => Feasibility { 
+                    possible: false, 
+                    reason: format!("{} files need about {} directory entries, but only about {} are free", count, needed, free) 
+                }
+            }
+        },
+        Err(_) if !Path::new(&dir).exists() => Feasibility { 
+            possible: count * fat32_entries_for(name_length) + 2 <= FAT32_MAX_DIR_ENTRIES, 
+            reason: "the directory does not exist yet".to_string() 
+        },
+        Err(e) => Feasibility { possible: true, reason: format!("the directory could not be inspected ({}), so this is unknown", e) }
+    };
+    return match serde_json::to_string(&feasibility) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json Feasibility. Error: {}", e))
+    }
+}
+
+pub fn relative_path(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let from = crate::sandbox::normalize_path(&args[0])?;
+    let to = crate::sandbox::normalize_path(&args[1])?;
+
+    // paths on different roots (such as `sd:/` and `rom:/`) can't be relative to 
+    // each other, so the destination is given absolutely instead
+    let root_of = |path: &str| path.find(":/").map(|index| path[..index].to_string());
+    if root_of(&from) != root_of(&to) {
+        return Ok(to);
+    }
+    let from_parts: Vec<&str> = from.split('/').filter(|part| !part.is_empty()).collect();
+    let to_parts: Vec<&str> = to.split('/').filter(|part| !part.is_empty()).collect();
+    let common = from_parts.iter().zip(to_parts.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<&str> = vec![".."; from_parts.len() - common];
+    parts.extend_from_slice(&to_parts[common..]);
+    return match parts.is_empty() {
+        true => Ok(".".to_string()),
+        false => Ok(parts.join("/"))
+    }
+}
+
+pub fn create_hardlink(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let source = args[0].clone();
+    let destination = args[1].clone();
+    if !Path::new(&source).exists() {
+        return Err(format!("file {} does not exist!", source));
+    }
+    if !Path::new(&source).is_file() {
+        return Err(format!("path {} is not a file!", source));
+    }
+    if Path::new(&destination).exists() {
+        return Err(format!("path {} already exists!", destination));
+    }
+
+    return match fs::hard_link(&source, &destination) {
+        Ok(_) => Ok("The hard link was created successfully".to_string()),
+        // filesystems without hard links (such as FAT) refuse with one of these
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported || e.kind() == std::io::ErrorKind::PermissionDenied => {
+            Err(format!("unsupported: this filesystem does not allow hard links ({})", e))
+        },
+        Err(e) => Err(format!("Could not create hard link. Reason: {:?}", e))
+    }
+}
+
+pub fn mkdir(context: &mut MessageContext) -> Result<String, String> {
+    let dir = &context.arguments.as_ref().unwrap()[0];
+    return match std::fs::create_dir_all(dir) {
+        Ok(ok) => Ok(format!("{:?}", ok)),
+        Err(err) => Ok(format!("{:?}", err)),
+    };
+}
+
+pub fn list_all_files(context: &mut MessageContext, writer: &mut ResponseWriter) -> Result<(), String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    if !Path::new(&path).exists() {
+        return Err(format!("path {} does not exist!", path));
+    }
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+
+    let mut subtree = DirTree::new(path.clone());
+    readDirAll(path, &mut subtree);
+    
+    // write the json straight into the response, since trees can be huge
+    return match serde_json::to_writer(writer, &subtree) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Could not serialize to json DirTree. Error: {}", e))
+    }
+}
+
+pub fn list_all_paths(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let path = args[0].clone();
+    if !Path::new(&path).exists() {
+        return Err(format!("path {} does not exist!", path));
+    }
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+    let filter = match args.get(1) {
+        Some(pattern) => match glob::Pattern::new(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => return Err(format!("invalid glob pattern {}: {}", pattern, e))
+        },
+        None => None
+    };
+
+    let mut paths = Vec::new();
+    for entry in WalkDir::new(&path).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = match entry.path().strip_prefix(&path) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue
+        };
+        if let Some(ref pattern) = filter {
+            if !pattern.matches(&relative) {
+                continue;
+            }
+        }
+        paths.push(relative);
+    }
+
+    return match serde_json::to_string(&paths) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json path list. Error: {}", e))
+    }
+}
+
+/// how deep into a directory tree `find_files` will search
+const MAX_SEARCH_DEPTH: usize = 32;
+/// how many matches `find_files` returns per page by default
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// whether the given file contains the given bytes, reading it in 
+/// chunks rather than loading the whole file.
+fn file_contains(path: &Path, needle: &[u8]) -> std::io::Result<bool> {
+    let mut file = fs::File::open(path)?;
+    let mut window: Vec<u8> = Vec::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        window.extend_from_slice(&buffer[..read]);
+        if window.windows(needle.len()).any(|candidate| candidate == needle) {
+            return Ok(true);
+        }
+        // keep just enough of the tail to catch matches spanning two chunks
+        let keep = (needle.len() - 1).min(window.len());
+        window.drain(..window.len() - keep);
+    }
+}
+
+pub fn list_all_text(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 4)?;
+    let path = args[0].clone();
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+    // empty optional arguments are treated as absent, so that later ones can be given
+    let filter = match args.get(1).filter(|pattern| !pattern.is_empty()) {
+        Some(pattern) => match glob::Pattern::new(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => return Err(format!("invalid glob pattern {}: {}", pattern, e))
+        },
+        None => None
+    };
+    let max_depth = match args.get(2).filter(|depth| !depth.is_empty()).map(|depth| depth.parse::<usize>()) {
+        None => usize::MAX,
+        Some(Ok(depth)) => depth,
+        Some(Err(_)) => return Err(format!("invalid depth: {}", args[2]))
+    };
+    let indent = parse_flag(args.get(3));
+
+    // without indentation, this lists the relative path of every file (like list_all_paths).
+    // With it, directories are listed too, and each entry is indented under its parent.
+    let mut text = String::new();
+    for entry in WalkDir::new(&path).min_depth(1).max_depth(max_depth).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        let relative = match entry.path().strip_prefix(&path) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue
+        };
+        let is_dir = entry.file_type().is_dir();
+        if !is_dir {
+            if let Some(ref pattern) = filter {
+                if !pattern.matches(&relative) {
+                    continue;
+                }
+            }
+        }
+        if indent {
+            text.push_str(&"  ".repeat(entry.depth() - 1));
+            text.push_str(&entry.file_name().to_string_lossy());
+            if is_dir {
+                text.push('/');
+            }
+            text.push('\n');
+        } else if !is_dir {
+            text.push_str(&relative);
+            text.push('\n');
+        }
+    }
+    Ok(text)
+}
+
+pub fn find_files(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 2, 5)?;
+    let root = args[0].clone();
+    if !Path::new(&root).is_dir() {
+        return Err(format!("path {} is not a directory!", root));
+    }
+    let pattern = match glob::Pattern::new(&args[1]) {
+        Ok(pattern) => pattern,
+        Err(e) => return Err(format!("invalid glob pattern {}: {}", args[1], e))
+    };
+    let content = args.get(2).filter(|content| !content.is_empty());
+    let page = match args.get(3).map(|page| page.parse::<usize>()) {
+        None => 0,
+        Some(Ok(page)) => page,
+        Some(Err(_)) => return Err(format!("invalid page: {}", args[3]))
+    };
+    let page_size = match args.get(4).map(|size| size.parse::<usize>()) {
+        None => DEFAULT_PAGE_SIZE,
+        Some(Ok(size)) if size > 0 => size,
+        Some(_) => return Err(format!("invalid page size: {}", args[4]))
+    };
+
+    let skip = page * page_size;
+    let mut matched = 0;
+    let mut paths = Vec::new();
+    let mut has_more = false;
+    let entries = WalkDir::new(&root).max_depth(MAX_SEARCH_DEPTH).sort_by_file_name().into_iter();
+    // unreadable entries are skipped, rather than failing the search
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() || !pattern.matches(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        if let Some(content) = content {
+            if !file_contains(entry.path(), content.as_bytes()).unwrap_or(false) {
+                continue;
+            }
+        }
+        matched += 1;
+        if matched <= skip {
+            continue;
+        }
+        if paths.len() == page_size {
+            has_more = true;
+            break;
+        }
+        paths.push(entry.path().display().to_string());
+    }
+
+    let result = SearchPage { paths: paths, page: page, page_size: page_size, has_more: has_more };
+    return match serde_json::to_string(&result) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json SearchPage. Error: {}", e))
+    }
+}
+
+pub fn file_exists(context: &mut MessageContext) -> Result<String, String> {
+    file_exists_bool(context).map(|exists| exists.to_string())
+}
+
+pub fn file_exists_bool(context: &mut MessageContext) -> Result<bool, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let exists = Path::new(&path).exists() && Path::new(&path).is_file();
+    Ok(exists)
+}
+
+pub fn exists_many(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let paths = match serde_json::from_str::<Vec<String>>(&args[0]) {
+        Ok(paths) => paths,
+        Err(e) => return Err(format!("Could not parse list of paths. Error: {}", e))
+    };
+    // the paths are inside the json, so the sandbox is checked here
+    for path in &paths {
+        crate::sandbox::check_path(context.config, path)?;
+    }
+    let mut results = serde_json::Map::new();
+    for path in paths {
+        let kind = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => "dir",
+            Ok(_) => "file",
+            Err(_) => "none"
+        };
+        results.insert(path, serde_json::Value::from(kind));
+    }
+    return match serde_json::to_string(&results) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json map of paths. Error: {}", e))
+    }
+}
+
+pub fn common_base(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let paths = match serde_json::from_str::<Vec<String>>(&args[0]) {
+        Ok(paths) => paths,
+        Err(e) => return Err(format!("Could not parse list of paths. Error: {}", e))
+    };
+    let mut dirs = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let normalized = crate::sandbox::check_path(context.config, path)?;
+        // files (or paths which don't exist) count as being within their parent directory
+        let dir = match Path::new(&normalized).is_dir() {
+            true => normalized,
+            false => match normalized.rfind('/') {
+                Some(index) if !normalized[..index].ends_with(':') && index > 0 => normalized[..index].to_string(),
+                Some(index) => normalized[..index + 1].to_string(),
+                None => String::new()
+            }
+        };
+        dirs.push(dir);
+    }
+    Ok(crate::sandbox::common_base(&dirs))
+}
+
+/// whether the given file appears to be in use. This is best-effort: a file being 
+/// updated by another handler is always reported, but whether an exclusive open fails 
+/// for a file held open elsewhere depends on the platform (the switch refuses to open 
+/// a file for writing twice, while most desktop systems allow it).
+pub fn is_locked(context: &mut MessageContext) -> Result<bool, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    if !Path::new(&path).is_file() {
+        return Err(format!("requested file ({}) does not exist!", path));
+    }
+    if context.state.file_locks.is_locked(&path) {
+        return Ok(true);
+    }
+    // the probe handle is dropped (closing the file) right away
+    return match fs::OpenOptions::new().write(true).open(&path) {
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && platform::is_read_only(&path) => Ok(false),
+        Err(_) => Ok(true)
+    }
+}
+
+pub fn same_file(context: &mut MessageContext) -> Result<bool, String> {
+    let args = context.arguments.as_ref().unwrap();
+    crate::sandbox::same_file(&args[0], &args[1])
+}
+
+pub fn dir_exists(context: &mut MessageContext) -> Result<String, String> {
+    dir_exists_bool(context).map(|exists| exists.to_string())
+}
+
+pub fn dir_exists_bool(context: &mut MessageContext) -> Result<bool, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    let exists = Path::new(&path).exists() && Path::new(&path).is_dir();
+    Ok(exists)
+}
+
+pub fn list_romfs(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 0, 1)?;
+    let relative = args.first().cloned().unwrap_or_default();
+    let path = crate::platform::romfs_path(&relative)?;
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Could not read romfs directory {}. Reason: {:?}", path, e))
+    };
+
+    // paths are given relative to the romfs root, to be passed back to read_romfs
+    let mut list = Vec::new();
+    for entry in entries.flatten() {
+        let kind = match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => 1,
+            _ => 0
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let entry_path = match relative.trim_matches('/') {
+            "" => name,
+            dir => format!("{}/{}", dir, name)
+        };
+        list.push(PathEntry{path: entry_path, kind: kind});
+    }
+    return match serde_json::to_string(&PathList{list: list}) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json PathList. Error: {}", e))
+    }
+}
+
+pub fn read_romfs(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = crate::platform::romfs_path(&args[0])?;
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading romfs file {}, {}", path, e))
+    };
+    return match String::from_utf8(data) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(format!("romfs file {} is not valid UTF-8", path))
+    }
+}
+
+pub fn list_dir(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let path = args[0].clone();
+    if !Path::new(&path).exists() {
+        return Err(format!("path {} does not exist!", path));
+    }
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+
+    let paths = fs::read_dir(path).unwrap();
+    //println!("Paths...");
+    let mut vec = Vec::new();
+    for entry in paths {
+        let fullpath = entry.unwrap().path().display().to_string();
+        //println!("Path: {}", fullpath);
+        let md = fs::metadata(fullpath.clone()).unwrap();
+        let kind = match md.is_file() {
+            true => 0,
+            false => 1
+        };
+        let path_entry = PathEntry{path: fullpath, kind: kind};
+        vec.push(path_entry);
+    }
+    let path_list = PathList{list: vec};
+    let json = match serde_json::to_string(&path_list) {
         Ok(val) => val,
         Err(e) => {
             return Err(format!("Could not serialize to json PathList. Error: {}", e)); 
         }
     };
-    //println!("replying to list_dir with: {}", &json);
-    Ok(json)
+    //println!("replying to list_dir with: {}", &json);
+    Ok(json)
+}
+
+pub fn list_subdirs(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 3)?;
+    let path = args[0].clone();
+    let recursive = parse_flag(args.get(1));
+    let max_depth = match args.get(2).map(|depth| depth.parse::<usize>()) {
+        None => MAX_SEARCH_DEPTH,
+        Some(Ok(depth)) if depth > 0 => depth.min(MAX_SEARCH_DEPTH),
+        Some(_) => return Err(format!("invalid depth: {} (must be a positive number)", args[2]))
+    };
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+
+    // directories are listed relative to the given path, and unreadable ones are skipped
+    let walker = WalkDir::new(&path)
+        .min_depth(1)
+        .max_depth(if recursive { max_depth } else { 1 })
+        .sort_by_file_name();
+    let dirs: Vec<String> = walker.into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| entry.path().strip_prefix(&path).ok().map(|relative| relative.display().to_string()))
+        .collect();
+    return match serde_json::to_string(&dirs) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json list of directories. Error: {}", e))
+    }
+}
+
+/// the event topic which followed files' new text is pushed with
+const FOLLOW_TOPIC: &str = "follow";
+
+/// the most text sent for a followed file at a time, so a burst of output doesn't stall the engine
+const MAX_FOLLOW_READ: u64 = 64 * 1024;
+
+pub fn follow_file(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let path = args[0].clone();
+    let from_start = parse_flag(args.get(1));
+    let size = match fs::metadata(&path) {
+        Ok(md) if md.is_file() => md.len(),
+        _ => return Err(format!("file {} does not exist!", path))
+    };
+    context.state.last_follow += 1;
+    let id = format!("follow-{}", context.state.last_follow);
+    let offset = if from_start { 0 } else { size };
+    context.state.follows.insert(id.clone(), crate::state::FollowedFile { path: path, offset: offset });
+    Ok(id)
+}
+
+pub fn stop_follow(context: &mut MessageContext) -> Result<String, String> {
+    let id = &context.arguments.as_ref().unwrap()[0];
+    return match context.state.follows.remove(id) {
+        Some(_) => Ok(format!("stopped {}", id)),
+        None => Err(format!("no file is being followed with id {}", id))
+    }
+}
+
+/// sends whatever was appended to each followed file since it was last checked, as `follow`
+/// events. A file which shrank was truncated or replaced, so it is read from its start again.
+pub(crate) fn poll_follows(session: &skyline_web::WebSession, state: &mut crate::state::EngineState) {
+    for (id, follow) in state.follows.iter_mut() {
+        let size = match fs::metadata(&follow.path) {
+            Ok(md) => md.len(),
+            // the file may be in the middle of being rotated
+            Err(_) => continue
+        };
+        let reset = size < follow.offset;
+        if reset {
+            follow.offset = 0;
+        }
+        if size == follow.offset {
+            continue;
+        }
+        let mut file = match fs::File::open(&follow.path) {
+            Ok(file) => file,
+            Err(_) => continue
+        };
+        let mut data = Vec::new();
+        let read = std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(follow.offset))
+            .and_then(|_| file.take(MAX_FOLLOW_READ).read_to_end(&mut data));
+        if read.is_err() {
+            continue;
+        }
+        follow.offset += data.len() as u64;
+        let update = FollowUpdate { follow_id: id.clone(), text: String::from_utf8_lossy(&data).to_string(), reset: reset };
+        if let Ok(json) = serde_json::to_string(&update) {
+            crate::message::send_event(session, FOLLOW_TOPIC, &json);
+        }
+    }
+}
+
+/// the event topic which `stream_list_dir` pushes its batches with
+const LIST_DIR_BATCH_TOPIC: &str = "list_dir_batch";
+
+/// how many entries `stream_list_dir` pushes at a time, by default
+const DEFAULT_LIST_DIR_BATCH_SIZE: usize = 100;
+
+pub fn stream_list_dir(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let path = args[0].clone();
+    let batch_size = match args.get(1).map(|size| size.parse::<usize>()) {
+        None => DEFAULT_LIST_DIR_BATCH_SIZE,
+        Some(Ok(size)) if size > 0 => size,
+        Some(_) => return Err(format!("invalid batch size: {}", args[1]))
+    };
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Could not read directory {}. Reason: {:?}", path, e))
+    };
+
+    let mut batch = PathBatch { request_id: context.id.clone(), batch: 0, list: Vec::new() };
+    let mut count = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let kind = match entry.file_type().map(|file_type| file_type.is_file()) {
+            Ok(true) => 0,
+            _ => 1
+        };
+        batch.list.push(PathEntry { path: entry.path().display().to_string(), kind: kind });
+        count += 1;
+        if batch.list.len() >= batch_size {
+            if context.is_cancelled() {
+                return Err("stream_list_dir was cancelled".to_string());
+            }
+            send_path_batch(context, &mut batch)?;
+        }
+    }
+    if !batch.list.is_empty() {
+        send_path_batch(context, &mut batch)?;
+    }
+    // the response itself signals that every batch has been sent
+    Ok(count.to_string())
+}
+
+/// pushes the given batch to the frontend, and then empties it for the next batch
+fn send_path_batch(context: &MessageContext, batch: &mut PathBatch) -> Result<(), String> {
+    let json = match serde_json::to_string(batch) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Could not serialize to json PathBatch. Error: {}", e))
+    };
+    context.send_event(LIST_DIR_BATCH_TOPIC, &json);
+    batch.batch += 1;
+    batch.list.clear();
+    Ok(())
+}
+
+pub fn open_dir(context: &mut MessageContext) -> Result<String, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    if !Path::new(&path).is_dir() {
+        return Err(format!("path {} is not a directory!", path));
+    }
+    return match fs::read_dir(&path) {
+        Ok(entries) => Ok(context.state.open_dir_cursor(entries)),
+        Err(e) => Err(format!("Could not read directory {}. Reason: {:?}", path, e))
+    }
+}
+
+pub fn next_dir_entries(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let handle = args[0].clone();
+    let batch_size = match args[1].parse::<usize>() {
+        Ok(size) if size > 0 => size,
+        _ => return Err(format!("invalid batch size: {}", args[1]))
+    };
+    let cursor = match context.state.dir_cursors.get_mut(&handle) {
+        Some(cursor) => cursor,
+        None => return Err(format!("no directory is open with handle {} (it may have timed out)", handle))
+    };
+    cursor.last_used = std::time::Instant::now();
+
+    let mut list = Vec::new();
+    let mut done = false;
+    while list.len() < batch_size {
+        let entry = match cursor.entries.next() {
+            Some(Ok(entry)) => entry,
+            Some(Err(_)) => continue,
+            None => {
+                done = true;
+                break;
+            }
+        };
+        let kind = match entry.file_type().map(|file_type| file_type.is_file()) {
+            Ok(true) => 0,
+            _ => 1
+        };
+        list.push(PathEntry { path: entry.path().display().to_string(), kind: kind });
+    }
+    if done {
+        context.state.dir_cursors.remove(&handle);
+    }
+    return match serde_json::to_string(&DirEntries { list: list, done: done }) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json DirEntries. Error: {}", e))
+    }
+}
+
+pub fn close_dir(context: &mut MessageContext) -> Result<String, String> {
+    let handle = &context.arguments.as_ref().unwrap()[0];
+    return match context.state.dir_cursors.remove(handle) {
+        Some(_) => Ok(format!("closed {}", handle)),
+        None => Err(format!("no directory is open with handle {}", handle))
+    }
+}
+
+/// interprets an optional flag argument, which is enabled by `true` or `1`
+fn parse_flag(arg: Option<&String>) -> bool {
+    return match arg {
+        Some(flag) => flag.eq_ignore_ascii_case("true") || flag == "1",
+        None => false
+    }
+}
+
+/// returns a unique path for a new temporary file in the configured temp directory
+fn temp_file_path(context: &MessageContext, prefix: &str) -> Result<String, String> {
+    let temp_dir = context.config.temp_dir();
+    if let Err(e) = fs::create_dir_all(&temp_dir) {
+        return Err(format!("Could not create temp directory {}. Reason: {:?}", temp_dir, e));
+    }
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or(0);
+    let mut path = format!("{}/{}_{}.tmp", temp_dir, prefix, nanos);
+    let mut counter = 1;
+    while Path::new(&path).exists() {
+        path = format!("{}/{}_{}_{}.tmp", temp_dir, prefix, nanos, counter);
+        counter += 1;
+    }
+    Ok(path)
+}
+
+/// the size of each block written and read by `benchmark_io`
+const BENCHMARK_BLOCK_SIZE: usize = 1024 * 1024;
+/// the largest file which `benchmark_io` will write
+const MAX_BENCHMARK_SIZE: u64 = 1024 * 1024 * 1024;
+
+pub fn benchmark_io(context: &mut MessageContext) -> Result<String, String> {
+    let arg = &context.arguments.as_ref().unwrap()[0];
+    let size = match arg.parse::<u64>() {
+        Ok(size) if size > 0 && size <= MAX_BENCHMARK_SIZE => size,
+        _ => return Err(format!("invalid benchmark size: {} (must be from 1 to {} bytes)", arg, MAX_BENCHMARK_SIZE))
+    };
+    let path = temp_file_path(context, "benchmark")?;
+    check_space_reserve(context.config.space_reserve, &path, size)?;
+    let result = run_benchmark(context, &path, size);
+    let _ = fs::remove_file(&path);
+    let (write_time, read_time) = result?;
+
+    let megabytes = size as f64 / 1_000_000.0;
+    let benchmark = IoBenchmark {
+        size: size,
+        write_ms: write_time.as_millis() as u64,
+        read_ms: read_time.as_millis() as u64,
+        write_mb_per_sec: megabytes / write_time.as_secs_f64().max(0.000001),
+        read_mb_per_sec: megabytes / read_time.as_secs_f64().max(0.000001)
+    };
+    return match serde_json::to_string(&benchmark) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json IoBenchmark. Error: {}", e))
+    }
+}
+
+/// writes `size` bytes to the given path and reads them back, 
+/// returning how long the writing and the reading took.
+fn run_benchmark(context: &MessageContext, path: &str, size: u64) -> Result<(std::time::Duration, std::time::Duration), String> {
+    // vary the data, so that it can't be stored more cheaply than it would be read
+    let block: Vec<u8> = (0..BENCHMARK_BLOCK_SIZE).map(|index| (index % 251) as u8).collect();
+    let blocks = size.div_ceil(BENCHMARK_BLOCK_SIZE as u64);
+
+    let start = std::time::Instant::now();
+    let mut file = match fs::File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not create file {}. Reason: {:?}", path, e))
+    };
+    let mut remaining = size;
+    for index in 0..blocks {
+        if context.is_cancelled() {
+            return Err("benchmark was cancelled".to_string());
+        }
+        context.send_progress(Progress::new(
+            "Benchmarking".to_string(), 
+            format!("writing block {} of {}", index + 1, blocks), 
+            (index as f64)/(2.0 * blocks as f64)));
+        let count = remaining.min(BENCHMARK_BLOCK_SIZE as u64) as usize;
+        if let Err(e) = file.write_all(&block[..count]) {
+            return Err(format!("While writing file, {:?}", e));
+        }
+        remaining -= count as u64;
+    }
+    // make sure the data actually reached the storage before stopping the clock
+    if let Err(e) = file.sync_all() {
+        return Err(format!("While writing file, {:?}", e));
+    }
+    drop(file);
+    let write_time = start.elapsed();
+
+    let start = std::time::Instant::now();
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Could not open file {}. Reason: {:?}", path, e))
+    };
+    let mut buffer = vec![0u8; BENCHMARK_BLOCK_SIZE];
+    let mut index = 0;
+    loop {
+        if context.is_cancelled() {
+            return Err("benchmark was cancelled".to_string());
+        }
+        context.send_progress(Progress::new(
+            "Benchmarking".to_string(), 
+            format!("reading block {} of {}", (index + 1).min(blocks), blocks), 
+            0.5 + (index as f64)/(2.0 * blocks as f64)));
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(_) => index += 1,
+            Err(e) => return Err(format!("While reading file, {:?}", e))
+        }
+    }
+    Ok((write_time, start.elapsed()))
+}
+
+pub fn make_temp_file(context: &mut MessageContext) -> Result<String, String> {
+    let prefix = get_args(context, 0, 1)?.first().cloned().unwrap_or("temp".to_string());
+    let path = temp_file_path(context, &prefix)?;
+    if let Err(e) = fs::write(&path, "") {
+        return Err(format!("Could not create temp file {}. Reason: {:?}", path, e));
+    }
+    context.state.temp_paths.push(path.clone());
+    Ok(path)
+}
+
+pub fn make_temp_dir(context: &mut MessageContext) -> Result<String, String> {
+    let prefix = get_args(context, 0, 1)?.first().cloned().unwrap_or("temp".to_string());
+    let path = temp_file_path(context, &prefix)?;
+    if let Err(e) = fs::create_dir(&path) {
+        return Err(format!("Could not create temp directory {}. Reason: {:?}", path, e));
+    }
+    context.state.temp_paths.push(path.clone());
+    Ok(path)
+}
+
+/// performs a GET request by downloading the body to a temporary file, so that its raw
+/// bytes survive, and then gunzips it if needed. Since `Curler` does not expose the 
+/// response headers, gzip encoding is detected by the body's magic bytes instead.
+fn get_request_decompressed(context: &MessageContext, url: String) -> Result<String, String> {
+    let temp_path = temp_file_path(context, "get")?;
+    let progress = |total: f64, current: f64| {
+        context.send_progress(Progress::new(
+        "Performing GET".to_string(), 
+        "doing GET request".to_string(), 
+        current/total));
+    };
+    let result = Curler::new()
+        .progress_callback(&progress)
+        .download(url, temp_path.clone());
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Error during get, error name: {:?}", e));
+    }
+
+    let data = fs::read(&temp_path);
+    let _ = fs::remove_file(&temp_path);
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return Err(format!("While reading response body, {}", e))
+    };
+    let body = match data.starts_with(&[0x1f, 0x8b]) {
+        true => {
+            let mut decoded = Vec::new();
+            if let Err(e) = flate2::read::GzDecoder::new(&data[..]).read_to_end(&mut decoded) {
+                return Err(format!("Could not decompress gzip response. Error: {}", e));
+            }
+            decoded
+        },
+        false => data
+    };
+    return match String::from_utf8(body) {
+        Ok(body) => Ok(body),
+        Err(_) => Err("response body is not valid UTF-8".to_string())
+    }
+}
+
+pub fn get_request(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let url = args[0].clone();
+    if parse_flag(args.get(1)) {
+        return get_request_decompressed(context, url);
+    }
+
+    let progress = |total: f64, current: f64| {
+        context.send_progress(Progress::new(
+        "Performing GET".to_string(), 
+        "doing GET request".to_string(), 
+        current/total));
+    };
+
+    let result = Curler::new()
+        .progress_callback(&progress)
+        .get(url);
+
+    //println!("got result from GET");
+
+    return match result {
+        Ok(body) => Ok(body),
+        Err(e) => Err(format!("Error during get: {}", e))
+    }
+}
+
+/// the endpoint used by `check_connectivity` when no url is given
+/// (the switch's own connection test)
+const CONNECTIVITY_TEST_URL: &str = "http://ctest.cdn.nintendo.net/";
+
+pub fn check_connectivity(context: &mut MessageContext) -> Result<String, String> {
+    let url = match context.arguments.as_ref().and_then(|args| args.first()) {
+        Some(url) => url.clone(),
+        None => CONNECTIVITY_TEST_URL.to_string()
+    };
+
+    let start = std::time::Instant::now();
+    let result = Curler::new().get(url.clone());
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    let connectivity = match result {
+        Ok(_) => Connectivity { url: url, reachable: true, latency_ms: latency_ms, error: None },
+        Err(e) => Connectivity { url: url, reachable: false, latency_ms: latency_ms, error: Some(format!("{}", e)) }
+    };
+    return match serde_json::to_string(&connectivity) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json Connectivity. Error: {}", e))
+    }
+}
+
+/// the capabilities which are available if any of their handlers is registered
+const HANDLER_FEATURES: &[(&str, &[&str])] = &[
+    ("base64", &["read_file_full", "read_zip_entry", "patch_bytes"]),
+    ("sha256", &["download_and_verify", "download_file"]),
+    ("zip", &["unzip", "can_install", "check_zip_safety", "read_zip_entries"]),
+    ("compression", &["compress_file", "decompress_file"]),
+    ("async", &["start_job"]),
+    ("events", &["subscribe"]),
+    ("follow", &["follow_file"]),
+];
+
+/// the features of this backend: those compiled in, those enabled by its 
+/// configuration, and those provided by its registered handlers
+fn enabled_features(context: &MessageContext) -> Vec<String> {
+    let mut features = Vec::new();
+    // there are no optional cargo features, but the platform's FFI (such 
+    // as for memory info and free space) is only available on the switch
+    if cfg!(target_os = "switch") {
+        features.push("switch");
+    }
+    if context.config.compression_threshold.is_some() {
+        features.push("response_compression");
+    }
+    if !context.config.sandbox_roots.is_empty() {
+        features.push("sandbox");
+    }
+    if context.config.safe_mode {
+        features.push("safe_mode");
+    }
+    for (feature, handlers) in HANDLER_FEATURES {
+        if handlers.iter().any(|handler| context.state.handler_schemas.contains_key(*handler)) {
+            features.push(feature);
+        }
+    }
+    features.into_iter().map(|feature| feature.to_string()).collect()
+}
+
+pub fn capabilities(context: &mut MessageContext) -> Result<String, String> {
+    let handlers = context.state.handler_schemas.values()
+        .map(|schema| HandlerInfo { name: schema.name.clone(), arg_count: schema.arg_count })
+        .collect();
+    let capabilities = Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        handlers: handlers,
+        features: enabled_features(context)
+    };
+    return match serde_json::to_string(&capabilities) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json Capabilities. Error: {}", e))
+    }
+}
+
+pub fn get_config(context: &mut MessageContext) -> Result<String, String> {
+    let mut config = context.config.effective();
+    // the working directory may have been changed since the engine was configured
+    config.config_dir = Some(context.config_dir());
+    config.trash_dir = context.trash_dir();
+    let settings = EngineSettings {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        chunk_size: crate::message::CHUNK_SIZE,
+        config: config
+    };
+    return match serde_json::to_string(&settings) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json EngineSettings. Error: {}", e))
+    }
+}
+
+pub fn get_schema(context: &mut MessageContext) -> Result<String, String> {
+    let schema = Schema {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        handlers: context.state.handler_schemas.values().cloned().collect()
+    };
+    return match serde_json::to_string(&schema) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json Schema. Error: {}", e))
+    }
+}
+
+pub fn support_bundle(context: &mut MessageContext) -> Result<String, String> {
+    let output = get_args(context, 0, 1)?.first().cloned();
+    let handlers = context.state.handler_schemas.values()
+        .map(|schema| HandlerInfo { name: schema.name.clone(), arg_count: schema.arg_count })
+        .collect();
+    let mut recent_errors: Vec<_> = context.state.recent_errors.iter().cloned().collect();
+    for error in recent_errors.iter_mut() {
+        error.message = redact_urls(&error.message);
+    }
+    let mut slow_operations = context.state.slow_operations.clone();
+    for operation in slow_operations.iter_mut() {
+        operation.arguments = operation.arguments.iter().map(|arg| redact_urls(arg)).collect();
+    }
+    let bundle = SupportBundle {
+        generated: unix_timestamp(),
+        capabilities: Capabilities {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            handlers: handlers,
+            features: enabled_features(context)
+        },
+        title: platform::title_info(),
+        memory: platform::memory_info(),
+        sd_free_space: platform::free_space("sd:/"),
+        config: context.config.clone(),
+        recent_errors: recent_errors,
+        slow_operations: slow_operations,
+        last_shutdown: context.state.last_shutdown.clone()
+    };
+    let json = match serde_json::to_string_pretty(&bundle) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Could not serialize to json SupportBundle. Error: {}", e))
+    };
+    return match output {
+        Some(path) => {
+            write_atomic(&path, json.as_bytes())?;
+            Ok(path)
+        },
+        None => Ok(json)
+    }
+}
+
+/// replaces the query string of any url in the given text, since 
+/// that is where tokens and other credentials tend to be passed
+fn redact_urls(text: &str) -> String {
+    text.split(' ')
+        .map(|word| match (word.contains("://"), word.find('?')) {
+            (true, Some(index)) => format!("{}?<redacted>", &word[..index]),
+            _ => word.to_string()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn frontend_log(context: &mut MessageContext) -> Result<String, String> {
+    let args = get_args(context, 1, 2)?;
+    let level = match args.get(1).map(|level| level.to_lowercase()) {
+        None => log::Level::Info,
+        Some(level) => match level.as_str() {
+            "info" => log::Level::Info,
+            "warn" | "warning" => log::Level::Warn,
+            "error" => log::Level::Error,
+            _ => return Err(format!("unknown log level: {} (expected info, warn or error)", level))
+        }
+    };
+
+    // without a logger installed, the `log` macros go nowhere,
+    // so fall back to printing as before.
+    if log::max_level() == log::LevelFilter::Off {
+        println!("Frontend Log [{}]: {}", level, args[0]);
+    } else {
+        log::log!(level, "Frontend Log: {}", args[0]);
+    }
+
+    if let Some(ref dir) = context.config.log_dir {
+        let line = format!("[{}] [{}] {}", unix_timestamp(), level, args[0]);
+        let path = Path::new(dir).join("frontend.log");
+        if let Err(e) = log_file::append_line(&path, &line, context.config.log_max_size, context.config.log_backups) {
+            return Err(format!("Could not write to log file. Reason: {:?}", e));
+        }
+    }
+    Ok("ok".to_string())
 }
 
-pub fn get_request(context: &mut MessageContext) -> Result<String, String> {
+pub fn log_json(context: &mut MessageContext) -> Result<String, String> {
+    let dir = match context.config.log_dir {
+        Some(ref dir) => dir.clone(),
+        None => return Err("no log file is configured, see with_log_file()".to_string())
+    };
+    let arg = &context.arguments.as_ref().unwrap()[0];
+    let mut entry = match serde_json::from_str::<serde_json::Value>(arg) {
+        Ok(serde_json::Value::Object(entry)) => entry,
+        Ok(_) => return Err("the log entry must be a JSON object".to_string()),
+        Err(e) => return Err(format!("the log entry is not valid JSON. Error: {}", e))
+    };
+    entry.insert("backend_timestamp".to_string(), serde_json::Value::from(unix_timestamp()));
+    // serializing without pretty printing keeps the entry on a single line
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(e) => return Err(format!("Could not serialize to json log entry. Error: {}", e))
+    };
+    let path = Path::new(&dir).join("frontend.jsonl");
+    if let Err(e) = log_file::append_line(&path, &line, context.config.log_max_size, context.config.log_backups) {
+        return Err(format!("Could not write to log file. Reason: {:?}", e));
+    }
+    Ok("ok".to_string())
+}
+
+pub fn validate_path(context: &mut MessageContext) -> Result<String, String> {
+    let path = context.arguments.as_ref().unwrap()[0].clone();
+    let validation = match crate::sandbox::check_path(context.config, &path) {
+        Ok(canonical) => PathValidation { path: path, permitted: true, canonical: Some(canonical), reason: None },
+        Err(reason) => PathValidation { path: path, permitted: false, canonical: None, reason: Some(reason) }
+    };
+    return match serde_json::to_string(&validation) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json PathValidation. Error: {}", e))
+    }
+}
+
+pub fn realpath(context: &mut MessageContext) -> Result<String, String> {
+    let path = &context.arguments.as_ref().unwrap()[0];
+    crate::sandbox::real_path(path)
+}
+
+pub fn start_job(context: &mut MessageContext) -> Result<String, String> {
     let args = context.arguments.as_ref().unwrap();
-    let url = args[0].clone();
+    let kind = args[0].to_lowercase();
+    let mut first = args[1].clone();
+    let mut second = args[2].clone();
+    // which arguments are paths depends on the kind, so the sandbox is checked here
+    if kind != "download" {
+        first = crate::sandbox::check_path(context.config, &first)?;
+    }
+    if kind != "hash" {
+        second = crate::sandbox::check_path(context.config, &second)?;
+    }
 
-    let progress = |total: f64, current: f64| {
-        context.send_progress(Progress::new(
-        "Performing GET".to_string(), 
-        "doing GET request".to_string(), 
-        current/total));
+    let reserve = context.config.space_reserve;
+    match kind.as_str() {
+        "download" => check_space_reserve(reserve, &second, 0)?,
+        "extract" if reserve.is_some() => check_space_reserve(reserve, &second, zip_install_size(&first)?.1)?,
+        _ => {}
+    }
+
+    let id = match kind.as_str() {
+        // downloads a url (first) to a location (second)
+        "download" => {
+            let temp_path = format!("{}.part", second);
+            context.state.jobs.start(&kind, move |job| {
+                let progress = |total: f64, current: f64| {
+                    job.set_progress("downloading", current/total);
+                };
+                // Curler can't be interrupted, so a cancelled download stops once it completes
+                let result = Curler::new()
+                    .progress_callback(&progress)
+                    .download(first, temp_path.clone());
+                if let Err(e) = result {
+                    let _ = fs::remove_file(&temp_path);
+                    return Err(format!("Error during download, error name: {:?}", e));
+                }
+                if job.is_cancelled() {
+                    let _ = fs::remove_file(&temp_path);
+                    return Err("download was cancelled".to_string());
+                }
+                if let Err(e) = check_space_reserve(reserve, &second, 0) {
+                    let _ = fs::remove_file(&temp_path);
+                    return Err(e);
+                }
+                let _ = fs::remove_file(&second);
+                match fs::rename(&temp_path, &second) {
+                    Ok(_) => Ok(second),
+                    Err(e) => Err(format!("Could not move download into place. Reason: {:?}", e))
+                }
+            })
+        },
+        // extracts a zip (first) into a directory (second)
+        "extract" => {
+            let config = context.config.clone();
+            context.state.jobs.start(&kind, move |job| {
+                let progress = |name: &str, progress: f64| {
+                    if job.is_cancelled() {
+                        return Err("extraction was cancelled".to_string());
+                    }
+                    job.set_progress(&format!("extracting {}", name), progress);
+                    Ok(())
+                };
+                let mut created = Vec::new();
+                let result = extract_zip(&first, &second, &config, &progress, &mut created);
+                // undo a cancelled extraction, leaving any files which were already there
+                if result.is_err() && job.is_cancelled() {
+                    for path in created {
+                        let _ = fs::remove_file(path);
+                    }
+                }
+                result.map(|_| second)
+            })
+        },
+        // hashes a file (first) with an algorithm (second)
+        "hash" => context.state.jobs.start(&kind, move |job| {
+            job.set_progress("hashing", 0.0);
+            let hash = hash_file(&first, &second)?;
+            match job.is_cancelled() {
+                true => Err("hashing was cancelled".to_string()),
+                false => Ok(hash)
+            }
+        }),
+        _ => return Err(format!("unknown job kind: {} (expected download, extract or hash)", kind))
     };
+    Ok(id)
+}
 
-    let result = Curler::new()
-        .progress_callback(&progress)
-        .get(url);
+pub fn cancel_job(context: &mut MessageContext) -> Result<String, String> {
+    let id = &context.arguments.as_ref().unwrap()[0];
+    context.state.jobs.cancel(id)?;
+    Ok(format!("cancellation of job {} requested", id))
+}
 
-    //println!("got result from GET");
+pub fn job_status(context: &mut MessageContext) -> Result<String, String> {
+    let id = &context.arguments.as_ref().unwrap()[0];
+    let status = match context.state.jobs.status(id) {
+        Some(status) => status,
+        None => return Err(format!("no job with id {} is known", id))
+    };
+    return match serde_json::to_string(&status) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json JobStatus. Error: {}", e))
+    }
+}
 
-    return match result {
-        Ok(body) => Ok(body),
-        Err(e) => Err(format!("Error during get: {}", e))
+pub fn get_config_dir(context: &mut MessageContext) -> Result<String, String> {
+    let dir = context.config_dir();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Could not create config directory {}. Reason: {:?}", dir, e));
+    }
+    Ok(dir)
+}
+
+pub fn set_config_dir(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let dir = args[0].trim_end_matches('/').to_string();
+    if dir.is_empty() {
+        return Err("the config directory cannot be empty".to_string());
+    }
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Could not create config directory {}. Reason: {:?}", dir, e));
+    }
+    // make sure we can actually write there before switching
+    let probe = format!("{}/.write_test", dir);
+    if let Err(e) = fs::write(&probe, "") {
+        return Err(format!("config directory {} is not writable. Reason: {:?}", dir, e));
+    }
+    let _ = fs::remove_file(&probe);
+
+    if let Some(parent) = Path::new(crate::config::CONFIG_DIR_POINTER).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    write_atomic(crate::config::CONFIG_DIR_POINTER, dir.as_bytes())?;
+    context.state.config_dir = Some(dir.clone());
+    Ok(dir)
+}
+
+pub fn clear_cache(context: &mut MessageContext) -> Result<String, String> {
+    let name = get_args(context, 0, 1)?.first().cloned();
+    // clear only the given handler's responses, or else everything
+    let cleared = match name {
+        Some(name) => context.state.result_cache.remove(&name).map(|cache| cache.len()).unwrap_or(0),
+        None => context.state.result_cache.drain().map(|(_, cache)| cache.len()).sum()
+    };
+    Ok(cleared.to_string())
+}
+
+pub fn subscribe(context: &mut MessageContext) -> Result<String, String> {
+    let topic = context.arguments.as_ref().unwrap()[0].clone();
+    context.state.subscriptions.insert(topic.clone());
+    Ok(topic)
+}
+
+pub fn unsubscribe(context: &mut MessageContext) -> Result<String, String> {
+    let topic = context.arguments.as_ref().unwrap()[0].clone();
+    return match context.state.subscriptions.remove(&topic) {
+        true => Ok(topic),
+        false => Err(format!("not subscribed to {}", topic))
+    }
+}
+
+pub fn get_recent_errors(context: &mut MessageContext) -> Result<String, String> {
+    if context.config.error_history_size == 0 {
+        return Err("error history is not enabled!".to_string());
+    }
+    return match serde_json::to_string(&context.state.recent_errors) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json error list. Error: {}", e))
+    }
+}
+
+pub fn get_result(context: &mut MessageContext) -> Result<String, String> {
+    if context.config.result_history_size == 0 {
+        return Err("result history is not enabled!".to_string());
+    }
+    let id = &context.arguments.as_ref().unwrap()[0];
+    return match context.state.stored_result(id) {
+        Some(Ok(crate::state::CachedResponse::Text(res))) => Ok(res.clone()),
+        Some(Ok(crate::state::CachedResponse::Bool(res))) => Ok(res.to_string()),
+        Some(Ok(crate::state::CachedResponse::Streamed(res))) => Ok(res.clone()),
+        Some(Err(err)) => Err(err.clone()),
+        None => Err(format!("no result is known for request {} (it may have expired)", id))
+    }
+}
+
+pub fn get_call_history(context: &mut MessageContext) -> Result<String, String> {
+    if context.config.call_history_size == 0 {
+        return Err("call history is not enabled!".to_string());
+    }
+    return match serde_json::to_string(&context.state.recent_calls) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json call list. Error: {}", e))
+    }
+}
+
+pub fn replay_call(context: &mut MessageContext) -> Result<String, String> {
+    if context.config.call_history_size == 0 {
+        return Err("call history is not enabled!".to_string());
+    }
+    let id = &context.arguments.as_ref().unwrap()[0];
+    let record = match context.state.recent_calls.iter().rev().find(|record| &record.id == id) {
+        Some(record) => record,
+        None => return Err(format!("no recorded call has the id {}", id))
+    };
+    if record.truncated {
+        return Err(format!("call {} can't be replayed, since its arguments were too long to record", id));
+    }
+    // the replay is handled once this request completes, and responds with its own id
+    let replay_id = format!("replay-{}-{}", id, unix_timestamp());
+    let message = crate::message::Message { id: replay_id.clone(), call_name: record.call_name.clone(), arguments: record.arguments.clone() };
+    let raw = match serde_json::to_string(&message) {
+        Ok(raw) => raw,
+        Err(e) => return Err(format!("Could not serialize to json Message. Error: {}", e))
+    };
+    context.state.pending_messages.get_mut().push_back(raw);
+    Ok(replay_id)
+}
+
+pub fn slow_operations(context: &mut MessageContext) -> Result<String, String> {
+    if context.config.slow_operation_count == 0 {
+        return Err("slow operation tracking is not enabled!".to_string());
+    }
+    return match serde_json::to_string(&context.state.slow_operations) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json operation list. Error: {}", e))
+    }
+}
+
+pub fn title_info() -> Result<String, String> {
+    return match serde_json::to_string(&platform::title_info()) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json TitleInfo. Error: {}", e))
+    }
+}
+
+pub fn clipboard_get() -> Result<String, String> {
+    platform::clipboard_get()
+}
+
+pub fn clipboard_set(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    platform::clipboard_set(&args[0])?;
+    Ok("ok".to_string())
+}
+
+pub fn engine_busy(context: &mut MessageContext) -> Result<bool, String> {
+    // this request is itself running, so only other requests count
+    Ok(context.state.busy.count() > 1 || context.state.jobs.any_running())
+}
+
+pub fn memory_info() -> Result<String, String> {
+    return match serde_json::to_string(&platform::memory_info()) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json MemoryInfo. Error: {}", e))
+    }
+}
+
+pub fn filesystem_info(context: &mut MessageContext) -> Result<String, String> {
+    let path = &context.arguments.as_ref().unwrap()[0];
+    return match serde_json::to_string(&platform::filesystem_info(path)) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json FilesystemInfo. Error: {}", e))
+    }
+}
+
+pub fn get_requests(context: &mut MessageContext) -> Result<String, String> {
+    let args = context.arguments.as_ref().unwrap();
+    let urls = match serde_json::from_str::<Vec<String>>(&args[0]) {
+        Ok(urls) => urls,
+        Err(e) => return Err(format!("Could not parse list of urls. Error: {}", e))
+    };
+
+    // requests run in batches of the configured download concurrency, and each 
+    // request gets its own result, so one failure does not fail the batch
+    let count = urls.len();
+    let batch_size = context.state.download_concurrency.max(1);
+    let mut results = Vec::new();
+    for batch in urls.chunks(batch_size) {
+        if context.is_cancelled() {
+            return Err("requests were cancelled".to_string());
+        }
+        context.send_progress(Progress::new(
+            "Performing GET".to_string(), 
+            format!("request {} of {}", results.len() + 1, count), 
+            (results.len() as f64)/(count as f64)));
+        let threads: Vec<_> = batch.iter().cloned().map(|url| std::thread::spawn(move || {
+            match Curler::new().get(url.clone()) {
+                Ok(body) => UrlResult { url: url, ok: true, message: body },
+                Err(e) => UrlResult { url: url, ok: false, message: format!("Error during get: {}", e) }
+            }
+        })).collect();
+        for (thread, url) in threads.into_iter().zip(batch) {
+            results.push(match thread.join() {
+                Ok(result) => result,
+                Err(_) => UrlResult { url: url.clone(), ok: false, message: "the request panicked".to_string() }
+            });
+        }
+    }
+
+    return match serde_json::to_string(&results) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json results. Error: {}", e))
+    }
+}
+
+pub fn get_download_concurrency(context: &mut MessageContext) -> Result<String, String> {
+    Ok(context.state.download_concurrency.max(1).to_string())
+}
+
+pub fn set_download_concurrency(context: &mut MessageContext) -> Result<String, String> {
+    let value = &context.arguments.as_ref().unwrap()[0];
+    let concurrency = match value.parse::<usize>() {
+        Ok(concurrency) if concurrency >= 1 => concurrency,
+        _ => return Err(format!("invalid download concurrency: {} (must be at least 1)", value))
+    };
+    context.state.download_concurrency = concurrency;
+    Ok(concurrency.to_string())
+}
+
+pub fn exit_session(context: &mut MessageContext) -> Result<String, String> {
+    if let Some(timeout_ms) = context.config.exit_ack_timeout_ms {
+        // acknowledge now, since the session will be closed before we could respond
+        context.return_ok("exiting session");
+        let timeout = std::time::Duration::from_millis(timeout_ms);
+        let start = std::time::Instant::now();
+        let mut acknowledged = false;
+        while !acknowledged && start.elapsed() < timeout {
+            match context.session.try_recv() {
+                Some(msg) => match serde_json::from_str::<Message>(&msg) {
+                    Ok(message) if message.call_name == "exit_session_ack" => acknowledged = true,
+                    // keep anything else, as requests arriving mid-request are kept
+                    _ => context.state.pending_messages.get_mut().push_back(msg)
+                },
+                None => std::thread::sleep(std::time::Duration::from_millis(10))
+            }
+        }
+        if !acknowledged {
+            log::warn!("No exit_session_ack was received in time, closing the session anyway.");
+        }
+    }
+    context.shutdown();
+    Ok("session should be closed, so this will never be sent".to_string())
+}
+
+pub fn get_shutdown_status(context: &mut MessageContext) -> Result<String, String> {
+    // the engine may have been restarted since, so fall back to the persisted status
+    let status = match context.state.last_shutdown {
+        Some(ref status) => Some(status.clone()),
+        None => crate::state::load_shutdown_status(&context.config_dir())
+    };
+    return match serde_json::to_string(&status) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Could not serialize to json shutdown status. Error: {}", e))
     }
 }
 
 pub(crate) fn register_defaults(engine: &mut RequestEngine) {
     // handler for a basic backend ping
     engine.register("ping", Some(0), |_|{pong()});
+    engine.register("capabilities", Some(0), |context| {
+        capabilities(context)
+    });
+    engine.register("get_config", Some(0), |context| {
+        get_config(context)
+    });
+    engine.register("get_schema", Some(0), |context| {
+        get_schema(context)
+    });
+    engine.register("support_bundle", None, |context| {
+        support_bundle(context)
+    });
     // handler for reading a file as a string
     engine.register("read_file", Some(1), |context| {
         read_file(context)
     });
+    engine.register("read_file_full", Some(1), |context| {
+        read_file_full(context)
+    });
+    engine.register("read_file_if_changed", Some(2), |context| {
+        read_file_if_changed(context)
+    });
+    engine.register("read_line", Some(2), |context| {
+        read_line(context)
+    });
+    engine.register("read_file_encoded", Some(2), |context| {
+        read_file_encoded(context)
+    });
+    engine.register("read_file_no_bom", Some(1), |context| {
+        read_file_no_bom(context)
+    });
     // handler for downloading a file to a location
-    engine.register("download_file", Some(2), |context| {
+    engine.register("download_file", None, |context| {
         download_file(context)
     });
     // handler for deleting a file
     engine.register("delete_file", Some(1), |context| {
         delete_file(context)
     });
+    // handler for downloading a file and verifying its hash
+    engine.register("download_and_verify", Some(3), |context| {
+        download_and_verify(context)
+    });
+    engine.register("trash_file", Some(1), |context| {
+        trash_file(context)
+    });
+    engine.register("archive_file", Some(1), |context| {
+        archive_file(context)
+    });
+    engine.register("restore_trashed", None, |context| {
+        restore_trashed(context)
+    });
+    engine.register("empty_trash", Some(0), |context| {
+        empty_trash(context)
+    });
     engine.register("write_file", Some(2), |context| {
         write_file(context)
     });
+    engine.register("write_file_sync", Some(2), |context| {
+        write_file_sync(context)
+    });
+    engine.register("patch_bytes", None, |context| {
+        patch_bytes(context)
+    });
+    engine.register("allocate_file", Some(2), |context| {
+        allocate_file(context)
+    });
+    engine.register("trim_file", None, |context| {
+        trim_file(context)
+    });
+    engine.register("write_file_encoded", Some(3), |context| {
+        write_file_encoded(context)
+    });
+    engine.register("format_json", Some(2), |context| {
+        format_json(context)
+    });
+    engine.register("replace_in_file", None, |context| {
+        replace_in_file(context)
+    });
+    engine.register("benchmark_io", Some(1), |context| {
+        benchmark_io(context)
+    });
     engine.register("get_md5", Some(1), |context| {
         get_md5(context)
     });
+    engine.register("block_hashes", Some(2), |context| {
+        block_hashes(context)
+    });
+    engine.register("dir_stats", None, |context| {
+        dir_stats(context)
+    });
+    engine.register("find_duplicates", Some(1), |context| {
+        find_duplicates(context)
+    });
+    engine.register("dir_hash", Some(1), |context| {
+        dir_hash(context)
+    });
+    engine.register("export_manifest", Some(2), |context| {
+        export_manifest(context)
+    });
+    engine.register("verify_manifest", Some(2), |context| {
+        verify_manifest(context)
+    });
+    engine.register("diff_files", Some(2), |context| {
+        diff_files(context)
+    });
+    engine.register("apply_patch", Some(2), |context| {
+        apply_patch(context)
+    });
     engine.register("unzip", Some(2), |context| {
         unzip(context)
     });
-    engine.register("file_exists", Some(1), |context| {
-        file_exists(context)
+    engine.register_bool("file_exists", Some(1), |context| {
+        file_exists_bool(context)
+    });
+    engine.register_bool("dir_exists", Some(1), |context| {
+        dir_exists_bool(context)
+    });
+    engine.register("exists_many", Some(1), |context| {
+        exists_many(context)
+    });
+    engine.register("common_base", Some(1), |context| {
+        common_base(context)
     });
-    engine.register("dir_exists", Some(1), |context| {
-        dir_exists(context)
+    engine.register_bool("is_locked", Some(1), |context| {
+        is_locked(context)
     });
-    engine.register("list_all_files", Some(1), |context| {
-        list_all_files(context)
+    engine.register_bool("same_file", Some(2), |context| {
+        same_file(context)
+    });
+    engine.register_streaming("list_all_files", Some(1), |context, writer| {
+        list_all_files(context, writer)
+    });
+    engine.register("list_all_paths", None, |context| {
+        list_all_paths(context)
+    });
+    engine.register("list_all_text", None, |context| {
+        list_all_text(context)
+    });
+    engine.register("find_files", None, |context| {
+        find_files(context)
+    });
+    engine.register("list_romfs", None, |context| {
+        list_romfs(context)
+    });
+    engine.register("read_romfs", Some(1), |context| {
+        read_romfs(context)
     });
     engine.register("list_dir", Some(1), |context| {
         list_dir(context)
     });
-    engine.register("get_request", Some(1), |context| {
+    engine.register("list_subdirs", None, |context| {
+        list_subdirs(context)
+    });
+    engine.register("stream_list_dir", None, |context| {
+        stream_list_dir(context)
+    });
+    engine.register("follow_file", None, |context| {
+        follow_file(context)
+    });
+    engine.register("stop_follow", Some(1), |context| {
+        stop_follow(context)
+    });
+    engine.register("open_dir", Some(1), |context| {
+        open_dir(context)
+    });
+    engine.register("next_dir_entries", Some(2), |context| {
+        next_dir_entries(context)
+    });
+    engine.register("close_dir", Some(1), |context| {
+        close_dir(context)
+    });
+    engine.register("make_temp_file", None, |context| {
+        make_temp_file(context)
+    });
+    engine.register("make_temp_dir", None, |context| {
+        make_temp_dir(context)
+    });
+    engine.register("get_request", None, |context| {
         get_request(context)
     });
+    engine.register("get_requests", Some(1), |context| {
+        get_requests(context)
+    });
+    engine.register("get_download_concurrency", Some(0), |context| {
+        get_download_concurrency(context)
+    });
+    engine.register("set_download_concurrency", Some(1), |context| {
+        set_download_concurrency(context)
+    });
+    engine.register("check_connectivity", None, |context| {
+        check_connectivity(context)
+    });
     engine.register("exit_session", None, |context| {
-        context.shutdown();
-        Ok("session should be closed, so this will never be sent".to_string())
+        exit_session(context)
+    });
+    engine.register("get_shutdown_status", Some(0), |context| {
+        get_shutdown_status(context)
     });
     engine.register("exit_application", None, |_context| {
-        unsafe { skyline::nn::oe::ExitApplication();}
-        // application is now closed, so we cannot return meaningfully.
+        platform::exit_application()
     });
     engine.register("log", None, |context| {
-        let args = context.arguments.as_ref().unwrap();
-        println!("Frontend Log: {}", args[0]);
-        Ok("ok".to_string())
+        frontend_log(context)
+    });
+    engine.register("log_json", Some(1), |context| {
+        log_json(context)
     });
     engine.register("mkdir", Some(1), |context| {mkdir(context)});
+    engine.register("rename", Some(2), |context| {
+        rename(context)
+    });
+    engine.register("split_file", Some(2), |context| {
+        split_file(context)
+    });
+    engine.register("join_files", None, |context| {
+        join_files(context)
+    });
+    engine.register("can_create_files", None, |context| {
+        can_create_files(context)
+    });
+    engine.register("relative_path", Some(2), |context| {
+        relative_path(context)
+    });
+    engine.register("create_hardlink", Some(2), |context| {
+        create_hardlink(context)
+    });
+    engine.register("can_install", Some(2), |context| {
+        can_install(context)
+    });
+    engine.register("check_zip_safety", None, |context| {
+        check_zip_safety(context)
+    });
+    engine.register("read_zip_entry", None, |context| {
+        read_zip_entry(context)
+    });
+    engine.register("read_zip_entries", Some(2), |context| {
+        read_zip_entries(context)
+    });
+    engine.register("compress_file", Some(3), |context| {
+        compress_file(context)
+    });
+    engine.register("decompress_file", Some(3), |context| {
+        decompress_file(context)
+    });
+    engine.register("merge_dir", None, |context| {
+        merge_dir(context)
+    });
+    engine.register("validate_path", Some(1), |context| {
+        validate_path(context)
+    });
+    engine.register("realpath", Some(1), |context| {
+        realpath(context)
+    });
+    engine.register("start_job", Some(3), |context| {
+        start_job(context)
+    });
+    engine.register("cancel_job", Some(1), |context| {
+        cancel_job(context)
+    });
+    engine.register("job_status", Some(1), |context| {
+        job_status(context)
+    });
+    engine.register("get_config_dir", Some(0), |context| {
+        get_config_dir(context)
+    });
+    engine.register("set_config_dir", Some(1), |context| {
+        set_config_dir(context)
+    });
+    engine.register("clear_cache", None, |context| {
+        clear_cache(context)
+    });
+    engine.register("subscribe", Some(1), |context| {
+        subscribe(context)
+    });
+    engine.register("unsubscribe", Some(1), |context| {
+        unsubscribe(context)
+    });
+    engine.register("get_recent_errors", Some(0), |context| {
+        get_recent_errors(context)
+    });
+    engine.register("get_result", Some(1), |context| {
+        get_result(context)
+    });
+    engine.register("get_call_history", Some(0), |context| {
+        get_call_history(context)
+    });
+    engine.register("replay_call", Some(1), |context| {
+        replay_call(context)
+    });
+    engine.register("slow_operations", Some(0), |context| {
+        slow_operations(context)
+    });
+    engine.register("clipboard_get", Some(0), |_| {clipboard_get()});
+    engine.register("clipboard_set", Some(1), |context| {
+        clipboard_set(context)
+    });
+    engine.register_bool("engine_busy", Some(0), |context| {
+        engine_busy(context)
+    });
+    engine.register("memory_info", Some(0), |_| {memory_info()});
+    engine.register("filesystem_info", Some(1), |context| {
+        filesystem_info(context)
+    });
+    engine.register("title_info", Some(0), |_| {title_info()});
+    engine.register("normalize_line_endings", Some(2), |context| {
+        normalize_line_endings(context)
+    });
+
+    // mark which arguments are paths, for the sandbox
+    for (name, requirements) in PATH_ARGUMENTS {
+        engine.set_path_requirements(*name, requirements);
+    }
 }
 
+/// the path arguments of each default handler, and what they must refer to
+const PATH_ARGUMENTS: &[(&str, &[(usize, PathKind)])] = &[
+    ("read_file", &[(0, PathKind::File)]),
+    ("read_file_if_changed", &[(0, PathKind::File)]),
+    ("read_file_full", &[(0, PathKind::File)]),
+    ("read_line", &[(0, PathKind::File)]),
+    ("read_file_encoded", &[(0, PathKind::File)]),
+    ("read_file_no_bom", &[(0, PathKind::File)]),
+    ("download_file", &[(1, PathKind::Any)]),
+    ("download_and_verify", &[(2, PathKind::Any)]),
+    ("delete_file", &[(0, PathKind::File)]),
+    ("trash_file", &[(0, PathKind::File)]),
+    ("archive_file", &[(0, PathKind::File)]),
+    ("restore_trashed", &[(1, PathKind::Any)]),
+    ("write_file", &[(0, PathKind::Any)]),
+    ("write_file_sync", &[(0, PathKind::Any)]),
+    ("patch_bytes", &[(0, PathKind::File)]),
+    ("allocate_file", &[(0, PathKind::Any)]),
+    ("trim_file", &[(0, PathKind::File)]),
+    ("is_locked", &[(0, PathKind::File)]),
+    ("filesystem_info", &[(0, PathKind::Any)]),
+    ("write_file_encoded", &[(0, PathKind::Any)]),
+    ("replace_in_file", &[(0, PathKind::File)]),
+    ("format_json", &[(0, PathKind::File)]),
+    ("get_md5", &[(0, PathKind::File)]),
+    ("block_hashes", &[(0, PathKind::File)]),
+    ("realpath", &[(0, PathKind::Exists)]),
+    ("same_file", &[(0, PathKind::Exists), (1, PathKind::Exists)]),
+    ("dir_hash", &[(0, PathKind::Dir)]),
+    ("dir_stats", &[(0, PathKind::Dir)]),
+    ("find_duplicates", &[(0, PathKind::Dir)]),
+    ("export_manifest", &[(0, PathKind::Dir), (1, PathKind::Any)]),
+    ("verify_manifest", &[(0, PathKind::Dir), (1, PathKind::File)]),
+    ("diff_files", &[(0, PathKind::File), (1, PathKind::File)]),
+    ("apply_patch", &[(0, PathKind::File)]),
+    ("unzip", &[(0, PathKind::File), (1, PathKind::Dir)]),
+    ("check_zip_safety", &[(0, PathKind::File)]),
+    ("read_zip_entry", &[(0, PathKind::File)]),
+    ("read_zip_entries", &[(0, PathKind::File)]),
+    ("can_install", &[(0, PathKind::File), (1, PathKind::Any)]),
+    ("file_exists", &[(0, PathKind::Any)]),
+    ("dir_exists", &[(0, PathKind::Any)]),
+    ("list_all_files", &[(0, PathKind::Dir)]),
+    ("list_all_paths", &[(0, PathKind::Dir)]),
+    ("list_all_text", &[(0, PathKind::Dir)]),
+    ("find_files", &[(0, PathKind::Dir)]),
+    ("list_dir", &[(0, PathKind::Dir)]),
+    ("list_subdirs", &[(0, PathKind::Dir)]),
+    ("stream_list_dir", &[(0, PathKind::Dir)]),
+    ("open_dir", &[(0, PathKind::Dir)]),
+    ("follow_file", &[(0, PathKind::File)]),
+    ("mkdir", &[(0, PathKind::Any)]),
+    ("rename", &[(0, PathKind::Exists), (1, PathKind::Any)]),
+    ("split_file", &[(0, PathKind::File)]),
+    ("join_files", &[(0, PathKind::Any)]),
+    ("can_create_files", &[(0, PathKind::Any)]),
+    ("create_hardlink", &[(0, PathKind::File), (1, PathKind::Any)]),
+    ("compress_file", &[(0, PathKind::File), (1, PathKind::Any)]),
+    ("decompress_file", &[(0, PathKind::File), (1, PathKind::Any)]),
+    ("merge_dir", &[(0, PathKind::Dir), (1, PathKind::Any)]),
+    ("normalize_line_endings", &[(0, PathKind::File)]),
+    ("support_bundle", &[(0, PathKind::Any)]),
+    ("set_config_dir", &[(0, PathKind::Any)]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{check_trashed_name, copy_file, date_string, extract_zip, manifest_entry_path, rename_path, sandboxed_paths, trashed_original_location, zstd_compress, zstd_decompress};
+    use crate::config::EngineConfig;
+    use std::io::Write;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn renames_case_only_via_intermediate() {
+        let dir = std::env::temp_dir().join("nx-request-handler-rename-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let from = dir.join("File.txt");
+        let to = dir.join("file.txt");
+        fs::write(&from, "contents").unwrap();
+
+        assert!(rename_path(&from, &to).unwrap());
+        let names: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["file.txt".to_string()]);
+        assert_eq!(fs::read_to_string(&to).unwrap(), "contents");
+
+        // other renames happen directly
+        assert!(!rename_path(&to, &dir.join("other.txt")).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn copies_preserving_timestamps() {
+        let dir = std::env::temp_dir().join("nx-request-handler-copy-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.txt");
+        fs::write(&source, "contents").unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+        fs::OpenOptions::new().write(true).open(&source).unwrap().set_modified(an_hour_ago).unwrap();
+        let modified = |path| fs::metadata(path).unwrap().modified().unwrap();
+
+        let preserved = dir.join("preserved.txt");
+        copy_file(&source, &preserved, true).unwrap();
+        assert_eq!(fs::read_to_string(&preserved).unwrap(), "contents");
+        // FAT only stores modified times to the nearest 2 seconds
+        let difference = modified(&preserved).duration_since(modified(&source))
+            .unwrap_or_else(|e| e.duration());
+        assert!(difference <= Duration::from_secs(2));
+
+        // without preserving, the copy is as new as the time it was made
+        let fresh = dir.join("fresh.txt");
+        copy_file(&source, &fresh, false).unwrap();
+        assert!(modified(&fresh) > modified(&source) + Duration::from_secs(60));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(date_string(0), "1970-01-01");
+        assert_eq!(date_string(951782400), "2000-02-29");
+        assert_eq!(date_string(1717243199), "2024-06-01");
+        assert_eq!(date_string(1735689600), "2025-01-01");
+    }
+
+    #[test]
+    fn refuses_zip_entries_escaping_destination() {
+        let dir = std::env::temp_dir().join("nx-request-handler-zip-test");
+        let _ = fs::remove_dir_all(&dir);
+        let destination = dir.join("mods");
+        fs::create_dir_all(&destination).unwrap();
+        let zip_path = dir.join("mod.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("ok.txt", options).unwrap();
+        zip.write_all(b"fine").unwrap();
+        zip.start_file("../escaped.txt", options).unwrap();
+        zip.write_all(b"escaped").unwrap();
+        zip.finish().unwrap();
+
+        let progress = |_: &str, _: f64| Ok(());
+        let destination = destination.display().to_string();
+        assert!(extract_zip(&zip_path.display().to_string(), &destination, &EngineConfig::default(), &progress, &mut Vec::new()).is_err());
+        // the archive is rejected as a whole, so not even the safe entry is extracted
+        assert!(!dir.join("escaped.txt").exists());
+        assert!(!dir.join("mods/ok.txt").exists());
+
+        // entries are also confined to the sandbox
+        let config = EngineConfig { sandbox_roots: vec![dir.join("other").display().to_string()], ..Default::default() };
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        zip.start_file("ok.txt", options).unwrap();
+        zip.finish().unwrap();
+        assert!(extract_zip(&zip_path.display().to_string(), &destination, &config, &progress, &mut Vec::new()).is_err());
+        assert!(extract_zip(&zip_path.display().to_string(), &destination, &EngineConfig::default(), &progress, &mut Vec::new()).is_ok());
+        assert!(dir.join("mods/ok.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn refuses_nested_paths_escaping_sandbox() {
+        let config = EngineConfig { sandbox_roots: vec!["sd:/ultimate/mods".to_string()], ..Default::default() };
+
+        // join_files' parts
+        assert_eq!(sandboxed_paths(&config, r#"["sd:/ultimate/mods/a.part0", "sd:/ultimate/mods/./a.part1"]"#).unwrap(), 
+            vec!["sd:/ultimate/mods/a.part0".to_string(), "sd:/ultimate/mods/a.part1".to_string()]);
+        assert!(sandboxed_paths(&config, r#"["sd:/ultimate/mods/a.part0", "sd:/ultimate/mods/../../atmosphere/a.part1"]"#).is_err());
 
+        // verify_manifest's entries
+        assert_eq!(manifest_entry_path(&config, "sd:/ultimate/mods/hdr", "fighter/mario.nutexb").unwrap(), "sd:/ultimate/mods/hdr/fighter/mario.nutexb");
+        assert!(manifest_entry_path(&config, "sd:/ultimate/mods/hdr", "../../../atmosphere/config.ini").is_err());
+        assert!(manifest_entry_path(&config, "sd:/ultimate/mods/hdr", "../hdr-other/file").is_err());
+
+        // restore_trashed's recorded original location
+        let dir = std::env::temp_dir().join("nx-request-handler-trash-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let info_path = dir.join("1_file.txt.trashinfo");
+        fs::write(&info_path, "sd:/ultimate/mods/file.txt").unwrap();
+        assert_eq!(trashed_original_location(&config, &info_path, "1_file.txt").unwrap(), "sd:/ultimate/mods/file.txt");
+        fs::write(&info_path, "sd:/atmosphere/contents/file.txt").unwrap();
+        assert!(trashed_original_location(&config, &info_path, "1_file.txt").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn accepts_only_plain_trashed_names() {
+        assert!(check_trashed_name("1717243199_config.json").is_ok());
+        assert!(check_trashed_name("../../atmosphere/config.ini").is_err());
+        assert!(check_trashed_name("sub/file.txt").is_err());
+        assert!(check_trashed_name("sub\\file.txt").is_err());
+        assert!(check_trashed_name("..").is_err());
+        assert!(check_trashed_name("").is_err());
+    }
+
+    #[test]
+    fn zstd_round_trips_across_frames() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        let mut compressed = Vec::new();
+        zstd_compress(data.as_slice(), &mut compressed).unwrap();
+        assert!(compressed.len() < data.len());
+        // two frames back to back, as a file larger than ZSTD_FRAME_SIZE is written
+        let mut frames = compressed.clone();
+        frames.extend_from_slice(&compressed);
+        let mut decompressed = Vec::new();
+        zstd_decompress(frames.as_slice(), &mut decompressed).unwrap();
+        assert_eq!(decompressed.len(), data.len() * 2);
+        assert_eq!(&decompressed[..data.len()], data.as_slice());
+        assert_eq!(&decompressed[data.len()..], data.as_slice());
+
+        let mut empty = Vec::new();
+        zstd_compress(&[][..], &mut empty).unwrap();
+        let mut decompressed = Vec::new();
+        zstd_decompress(empty.as_slice(), &mut decompressed).unwrap();
+        assert!(decompressed.is_empty());
+        assert!(zstd_decompress(&b"not zstd"[..], &mut Vec::new()).is_err());
+    }
+}
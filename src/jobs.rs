@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Serialize, Deserialize};
+use crate::concurrency::BusyCounter;
+
+/// how many finished jobs are remembered for `job_status`. Once there are more,
+/// the oldest are forgotten whenever a new job starts. Running jobs are always kept.
+const MAX_FINISHED_JOBS: usize = 16;
+
+/// the lifecycle of a background job
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+    Cancelled
+}
+
+/// a snapshot of a background job, as returned by `job_status`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JobStatus {
+    pub id: String,
+    /// what the job does, such as `download`
+    pub kind: String,
+    pub state: JobState,
+    /// a description of what the job is currently doing
+    pub phase: String,
+    /// the progress of the job, from 0 to 1
+    pub progress: f64,
+    /// the job's result, once it is done
+    pub result: Option<String>,
+    /// the job's error, if it failed
+    pub error: Option<String>
+}
+
+/// the jobs known to the engine, by id. Each job's status is shared
+/// with the thread running it, which updates it as the job progresses.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: BTreeMap<String, JobHandle>,
+    next_id: u64,
+    /// how many jobs are running, so that `any_running` doesn't have to lock each status
+    running: BusyCounter
+}
+
+/// given to a job's work, so that it can report its progress
+/// and check whether it has been cancelled
+#[derive(Clone)]
+pub struct JobHandle {
+    status: Arc<Mutex<JobStatus>>,
+    cancelled: Arc<AtomicBool>
+}
+
+impl JobHandle {
+    /// updates the job's current phase and progress (from 0 to 1)
+    pub fn set_progress(&self, phase: &str, progress: f64) {
+        let mut status = self.status.lock().unwrap();
+        status.phase = phase.to_string();
+        status.progress = progress;
+    }
+
+    /// whether `cancel_job` was called for this job. Work should check this at each 
+    /// checkpoint, and clean up and return an `Err` once it is true.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl JobRegistry {
+    /// runs the given work on a new thread, returning the job's id immediately. The job's
+    /// status records the work's result or error once it finishes. Jobs run to completion
+    /// even if the engine shuts down first.
+    pub fn start<F>(&mut self, kind: &str, work: F) -> String 
+        where F: FnOnce(&JobHandle) -> Result<String, String> + Send + 'static 
+    {
+        self.prune_finished();
+        self.next_id += 1;
+        let id = format!("job-{}", self.next_id);
+        let handle = JobHandle { status: Arc::new(Mutex::new(JobStatus {
+            id: id.clone(),
+            kind: kind.to_string(),
+            state: JobState::Running,
+            phase: "starting".to_string(),
+            progress: 0.0,
+            result: None,
+            error: None
+        })), cancelled: Arc::new(AtomicBool::new(false)) };
+        self.jobs.insert(id.clone(), handle.clone());
+
+        let busy = self.running.enter();
+        std::thread::spawn(move || {
+            let _busy = busy;
+            let result = work(&handle);
+            let mut status = handle.status.lock().unwrap();
+            match result {
+                Ok(result) => {
+                    status.state = JobState::Done;
+                    status.phase = "done".to_string();
+                    status.progress = 1.0;
+                    status.result = Some(result);
+                },
+                Err(error) if handle.is_cancelled() => {
+                    status.state = JobState::Cancelled;
+                    status.phase = "cancelled".to_string();
+                    status.error = Some(error);
+                },
+                Err(error) => {
+                    status.state = JobState::Failed;
+                    status.phase = "failed".to_string();
+                    status.error = Some(error);
+                }
+            }
+        });
+        id
+    }
+
+    /// forgets the oldest finished jobs beyond `MAX_FINISHED_JOBS`
+    fn prune_finished(&mut self) {
+        let mut finished: Vec<(u64, String)> = self.jobs.iter()
+            .filter(|(_, job)| job.status.lock().unwrap().state != JobState::Running)
+            .map(|(id, _)| (id.trim_start_matches("job-").parse().unwrap_or(0), id.clone()))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        for (_, id) in finished.iter().take(finished.len() - MAX_FINISHED_JOBS) {
+            self.jobs.remove(id);
+        }
+    }
+
+    /// returns a snapshot of the given job's status, if it is known
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        self.jobs.get(id).map(|job| job.status.lock().unwrap().clone())
+    }
+
+    /// whether any job is still running
+    pub fn any_running(&self) -> bool {
+        self.running.count() > 0
+    }
+
+    /// signals the given running job to stop at its next checkpoint
+    pub fn cancel(&self, id: &str) -> Result<(), String> {
+        let job = match self.jobs.get(id) {
+            Some(job) => job,
+            None => return Err(format!("no job with id {} is known", id))
+        };
+        if job.status.lock().unwrap().state != JobState::Running {
+            return Err(format!("job {} has already finished", id));
+        }
+        job.cancelled.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
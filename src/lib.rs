@@ -1,12 +1,27 @@
 use skyline_web::{WebSession};
 use std::{collections::HashMap};
+use std::time::{Duration, Instant};
 use crate::message::*;
+use crate::config::EngineConfig;
+use crate::state::{CachedResponse, EngineState, sanitize_arguments, unix_timestamp};
+use crate::response::{HandlerSchema, OperationRecord, PathArgumentSchema, ShutdownStatus};
+use crate::concurrency::ConcurrencyLimiter;
+pub use crate::concurrency::{Concurrency, FileLock};
+pub use crate::message::{MessageContext, ResponseWriter};
+pub use crate::sandbox::PathKind;
 use serde::{Serialize, Deserialize};
 
 mod response;
 mod message;
+mod config;
+mod state;
+mod concurrency;
 pub mod default_handlers;
 mod unzipper;
+mod platform;
+mod sandbox;
+mod jobs;
+mod log_file;
 
 /// progress data
 #[derive(Serialize, Deserialize)]
@@ -28,20 +43,321 @@ impl Progress {
 pub struct RequestEngine {
     is_exit: bool,
     session: WebSession,
-    handlers: HashMap<String, Handler>
+    handlers: HashMap<String, Handler>,
+    config: EngineConfig,
+    state: EngineState,
+    limiter: ConcurrencyLimiter,
+    raw_message_hook: Option<RawMessageHook>
 }
 
+/// called with each raw message from the frontend, see `with_raw_message_hook()`
+type RawMessageHook = Box<dyn Fn(&str)>;
+
 struct Handler {
     pub call_name: String,
     pub arg_count: Option<usize>,
-    pub callback:  Box<dyn Fn(&mut MessageContext) -> Result<String, String>>
+    pub concurrency: Concurrency,
+    /// the indices of the arguments which are paths, checked against the sandbox,
+    /// and what each must refer to in safe mode
+    pub path_arguments: Vec<(usize, PathKind)>,
+    /// how long successful responses are cached for, if at all
+    pub cache_ttl: Option<Duration>,
+    pub callback: Callback
+}
+
+/// An alternative to registering closures, for handlers which are better organized as types,
+/// such as handlers which carry their own state. Register implementors via `register_handler()`.
+/// 
+/// Example:
+/// ```
+/// struct Greeter { greeting: String }
+/// 
+/// impl RequestHandler for Greeter {
+///     fn handle(&self, context: &mut MessageContext) -> Result<String, String> {
+///         let args = context.arguments.as_ref().unwrap();
+///         Ok(format!("{}, {}!", self.greeting, args[0]))
+///     }
+///     fn arg_count(&self) -> Option<usize> {
+///         Some(1)
+///     }
+/// }
+/// 
+/// engine.register_handler("greet", Box::new(Greeter { greeting: "Hello".to_string() }));
+/// ```
+pub trait RequestHandler {
+    /// handles a request, exactly like the closure given to `register()`
+    fn handle(&self, context: &mut MessageContext) -> Result<String, String>;
+
+    /// the number of arguments to expect, as with the `arg_count` given to `register()`.
+    /// By default, arguments are not validated.
+    fn arg_count(&self) -> Option<usize> {
+        None
+    }
+
+    /// a descriptive name for this handler, used when logging
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+/// a callback returning the whole response at once
+type SimpleCallback = Box<dyn Fn(&mut MessageContext) -> Result<String, String>>;
+
+/// a callback writing its response incrementally
+type StreamingCallback = Box<dyn Fn(&mut MessageContext, &mut ResponseWriter) -> Result<(), String>>;
+
+/// a callback returning a boolean response
+type BoolCallback = Box<dyn Fn(&mut MessageContext) -> Result<bool, String>>;
+
+/// the kinds of callbacks which can be registered
+enum Callback {
+    /// returns the whole response at once
+    Simple(SimpleCallback),
+    /// returns a boolean, which is sent as a JSON boolean
+    Boolean(BoolCallback),
+    /// writes the response incrementally to a `ResponseWriter`
+    Streaming(StreamingCallback)
+}
+
+/// the successful outcome of a callback
+enum Response {
+    Text(String),
+    Bool(bool),
+    /// the response was already written to a `ResponseWriter`
+    Streamed
 }
 
 
 impl RequestEngine {
     /// Creates a new RequestEngine, taking ownership of the session in the process.
     pub fn new(session: WebSession) -> Self {
-        return RequestEngine{is_exit: false, session: session, handlers: HashMap::new()};
+        return RequestEngine{is_exit: false, session: session, handlers: HashMap::new(), config: EngineConfig::default(), state: EngineState::default(), limiter: ConcurrencyLimiter::new(), raw_message_hook: None};
+    }
+
+    /// Sets the directory which the `trash_file` default handler moves files into,
+    /// so that they can later be recovered with `restore_trashed`. If no trash directory is set,
+    /// `trash` in the working directory is used if one was chosen (see `with_config_dir()`), and 
+    /// otherwise `trash_file` deletes files permanently instead.
+    pub fn with_trash_dir<S: ToString>(&mut self, dir: S) -> &mut Self {
+        self.config.trash_dir = Some(dir.to_string());
+        return self;
+    }
+
+    /// Sets the directory which the `archive_file` default handler moves files into, 
+    /// under a subdirectory named for the current date (such as `2024-06-01`).
+    pub fn with_archive_dir<S: ToString>(&mut self, dir: S) -> &mut Self {
+        self.config.archive_dir = Some(dir.to_string());
+        return self;
+    }
+
+    /// Sets the directory which handlers stage temporary files in, such as the bodies
+    /// of `get_request` calls which must be decompressed. Defaults to `sd:/nx-request-handler/tmp`.
+    pub fn with_temp_dir<S: ToString>(&mut self, dir: S) -> &mut Self {
+        self.config.temp_dir = Some(dir.to_string());
+        return self;
+    }
+
+    /// Enables remembering the most recent errors returned to the frontend (up to `count`
+    /// of them), which can then be retrieved with the `get_recent_errors` default handler.
+    /// This is useful for diagnosing intermittent failures. Disabled by default.
+    pub fn with_error_history(&mut self, count: usize) -> &mut Self {
+        self.config.error_history_size = count;
+        return self;
+    }
+
+    /// Makes the `exit_session` default handler acknowledge the request before closing the
+    /// session, rather than closing it immediately. After acknowledging, the engine waits for
+    /// the frontend to send an `exit_session_ack` request (giving the UI time to show a clean
+    /// "goodbye" state), or for `timeout_ms` milliseconds to pass, whichever comes first.
+    pub fn with_exit_acknowledgement(&mut self, timeout_ms: u64) -> &mut Self {
+        self.config.exit_ack_timeout_ms = Some(timeout_ms);
+        return self;
+    }
+
+    /// Makes the `log` default handler also write each message to `frontend.log` in the given
+    /// directory. Once the file would grow beyond `max_size` bytes, it is rotated to `frontend.log.1`
+    /// (and so on), keeping at most `backups` rotated files. This gives a bounded on-device log,
+    /// without the frontend having to manage it. Structured entries from `log_json` are 
+    /// written to `frontend.jsonl` alongside it, and rotated in the same way.
+    pub fn with_log_file<S: ToString>(&mut self, dir: S, max_size: u64, backups: usize) -> &mut Self {
+        self.config.log_dir = Some(dir.to_string());
+        self.config.log_max_size = max_size;
+        self.config.log_backups = backups;
+        return self;
+    }
+
+    /// Sets a hook which is given every raw message received from the frontend, before it
+    /// is deserialized (and regardless of whether it deserializes successfully). This is useful
+    /// for debugging malformed requests, which are otherwise only logged in truncated form.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.with_raw_message_hook(|raw| println!("received: {}", raw));
+    /// ```
+    pub fn with_raw_message_hook(&mut self, hook: impl Fn(&str) + 'static) -> &mut Self {
+        self.raw_message_hook = Some(Box::new(hook));
+        return self;
+    }
+
+    /// Enables tracking of the `count` slowest handler invocations (with their durations and
+    /// truncated arguments), which can then be retrieved with the `slow_operations` default handler.
+    /// This helps identify which operations are slow on real hardware. Disabled by default.
+    pub fn with_slow_operation_tracking(&mut self, count: usize) -> &mut Self {
+        self.config.slow_operation_count = count;
+        return self;
+    }
+
+    /// Keeps the `.part` file of a failed `download_file`, rather than removing it, and reports
+    /// its path and size in the error. This is useful for diagnosing downloads which fail on 
+    /// specific networks, by examining how much data arrived.
+    pub fn with_partial_downloads_kept(&mut self) -> &mut Self {
+        self.config.keep_partial_downloads = true;
+        return self;
+    }
+
+    /// Persists the per-file hashes cached by `dir_hash` to the given file, so that 
+    /// repeated integrity checks stay fast across restarts. Without this, the 
+    /// cache only lives as long as the engine.
+    pub fn with_hash_cache_file(&mut self, path: &str) -> &mut Self {
+        self.config.hash_cache_file = Some(path.to_string());
+        return self;
+    }
+
+    /// Sets the backend's working directory (`sd:/nx-request-handler` by default), which the 
+    /// frontend can query with `get_config_dir`. Once set, the trash is kept in it, unless set with 
+    /// `with_trash_dir()`. Note that a directory chosen by the frontend with `set_config_dir` is 
+    /// persisted, and takes precedence over this.
+    pub fn with_config_dir(&mut self, dir: &str) -> &mut Self {
+        self.config.config_dir = Some(dir.to_string());
+        return self;
+    }
+
+    /// Restricts handlers to the given directories. Any path argument (see `set_path_arguments()`)
+    /// outside of these is rejected before the handler is called, and permitted paths are passed 
+    /// to the handler in normalized form. The frontend can check a path in advance with `validate_path`.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.with_sandbox(&["sd:/ultimate/mods", "sd:/nx-request-handler"]);
+    /// ```
+    pub fn with_sandbox(&mut self, roots: &[&str]) -> &mut Self {
+        self.config.sandbox_roots = roots.iter().map(|root| root.to_string()).collect();
+        return self;
+    }
+
+    /// Only handles the given calls, rejecting any others even if a handler is registered
+    /// for them. This is checked when each request is dispatched, independently of which
+    /// handlers were registered, so one configuration can switch off problematic calls.
+    pub fn with_allowed_calls(&mut self, calls: &[&str]) -> &mut Self {
+        self.config.allowed_calls = Some(calls.iter().map(|call| call.to_string()).collect());
+        return self;
+    }
+
+    /// Rejects the given calls, even if a handler is registered for them (and 
+    /// even if they are allowed by `with_allowed_calls()`).
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register_defaults()
+    ///     .with_denied_calls(&["exit_application", "empty_trash"]);
+    /// ```
+    pub fn with_denied_calls(&mut self, calls: &[&str]) -> &mut Self {
+        self.config.denied_calls = calls.iter().map(|call| call.to_string()).collect();
+        return self;
+    }
+
+    /// Removes anything in the temp directory (see `with_temp_dir()`) which was last modified
+    /// more than `max_age_secs` ago when `start()` is called, such as temporary files and staging 
+    /// directories left behind by a crash. Each removed path is logged. The `.part` files of 
+    /// `download_file` are written beside their destination instead, so they are not removed.
+    pub fn with_temp_cleanup(&mut self, max_age_secs: u64) -> &mut Self {
+        self.config.temp_cleanup_age_secs = Some(max_age_secs);
+        return self;
+    }
+
+    /// Enables safe mode, in which each path argument is checked to refer to what its handler
+    /// requires (see `set_path_requirements()`), such as an existing file, before the handler 
+    /// is called. Otherwise, the request is rejected with a JSON `PathError`, such as
+    /// `{"error":"not_found","path":"sd:/missing.txt","argument":0}`. This gives consistent
+    /// errors for every default handler. Arguments which may not exist yet (such as the 
+    /// destination of `write_file`) are not checked.
+    pub fn with_safe_mode(&mut self) -> &mut Self {
+        self.config.safe_mode = true;
+        return self;
+    }
+
+    /// Returns how the last attempt to shut down the engine went, if there was one. This is 
+    /// useful after `start()` returns, or unexpectedly fails to, to diagnose the session's exit.
+    pub fn last_shutdown(&self) -> Option<&ShutdownStatus> {
+        self.state.last_shutdown.as_ref()
+    }
+
+    /// Returns whether the engine is currently doing work, either running a handler or a 
+    /// background job (see `start_job`).
+    pub fn is_busy(&self) -> bool {
+        self.state.busy.count() > 0 || self.state.jobs.any_running()
+    }
+
+    /// Sets how long sending a message to the frontend may keep failing before the engine
+    /// considers the session closed and stops. The page may be briefly too busy to accept
+    /// messages (such as while it handles a large response), so this defaults to 
+    /// `DEFAULT_SEND_TIMEOUT_SECS`.
+    pub fn with_send_timeout(&mut self, timeout_secs: u64) -> &mut Self {
+        self.config.send_timeout_secs = Some(timeout_secs);
+        return self;
+    }
+
+    /// Makes the engine check, every `interval_secs` while waiting for a request, that the 
+    /// session is still open, by sending it a `heartbeat` event (which the frontend can ignore).
+    /// Otherwise, a page which closes while the engine waits for a request (such as when the 
+    /// user backs out of it) leaves `start()` waiting forever, since only sending reveals that
+    /// the session has closed.
+    pub fn with_session_probe(&mut self, interval_secs: u64) -> &mut Self {
+        self.config.session_probe_secs = Some(interval_secs.max(1));
+        return self;
+    }
+
+    /// Makes the default handlers which write files (such as `write_file`, `download_file` and
+    /// `unzip`) refuse to proceed if the free space where they write would drop below `reserve`
+    /// bytes, rejecting with an error starting with `low_space`. A completely full SD card risks
+    /// corrupting its filesystem, so this is a safety net beneath any checks of the frontend's own.
+    /// Where the free space can't be determined, writes are allowed.
+    pub fn with_space_reserve(&mut self, reserve: u64) -> &mut Self {
+        self.config.space_reserve = Some(reserve);
+        return self;
+    }
+
+    /// Sets the largest single argument (`max_argument_size`) and the largest total of a 
+    /// request's arguments (`max_arguments_size`) which are accepted, in bytes. Requests 
+    /// exceeding either are rejected before reaching any handler, with a JSON `ArgumentSizeError`
+    /// such as `{"error":"argument_too_large","argument":1,"size":40000000,"limit":33554432}`.
+    /// The defaults (32 MiB per argument, 64 MiB in total) leave room for writing large files 
+    /// with `write_file`; lower them to protect a memory-constrained plugin from a misbehaving
+    /// frontend, or raise them if the frontend legitimately sends more.
+    pub fn with_argument_limits(&mut self, max_argument_size: usize, max_arguments_size: usize) -> &mut Self {
+        self.config.max_argument_size = Some(max_argument_size);
+        self.config.max_arguments_size = Some(max_arguments_size);
+        return self;
+    }
+
+    /// Keeps the results of the `size` most recent requests for `ttl_secs` seconds, so that
+    /// the frontend can fetch a result again with `get_result` (by the original request's id) if
+    /// its response was lost, such as to a dropped connection, without running the handler again.
+    /// Only rely on this for results which are still meaningful when fetched later; a result 
+    /// describes the moment its request was handled, not the current state.
+    pub fn with_result_history(&mut self, size: usize, ttl_secs: u64) -> &mut Self {
+        self.config.result_history_size = size;
+        self.config.result_history_ttl_secs = ttl_secs;
+        return self;
+    }
+
+    /// Records the `size` most recent requests from the frontend (with long arguments truncated),
+    /// which can be listed with `get_call_history` and sent again with `replay_call`. This is 
+    /// meant for debugging, to reproduce the sequence of requests which led to a bug, and should
+    /// not be enabled in release builds.
+    pub fn with_call_history(&mut self, size: usize) -> &mut Self {
+        self.config.call_history_size = size;
+        return self;
     }
 
     /// Registers a handler for requests with the given name.
@@ -70,46 +386,448 @@ impl RequestEngine {
         &mut self, request_name: S, 
         arg_count: Option<usize>, 
         handler: impl Fn(&mut MessageContext)-> Result<String, String> + 'static) -> &mut Self {
-        let name = request_name.to_string();
+        return self.insert_handler(request_name.to_string(), arg_count, Callback::Simple(Box::new(handler)));
+    }
+
+    fn insert_handler(&mut self, name: String, arg_count: Option<usize>, callback: Callback) -> &mut Self {
+        // handlers can't see the engine, so keep a summary of them in the state
+        let returns = match callback {
+            Callback::Simple(_) => "string",
+            Callback::Boolean(_) => "boolean",
+            Callback::Streaming(_) => "stream"
+        };
+        self.state.handler_schemas.insert(name.clone(), HandlerSchema { 
+            name: name.clone(), 
+            arg_count: arg_count, 
+            returns: returns.to_string(), 
+            path_arguments: Vec::new() 
+        });
         self.handlers.insert(name.clone(), Handler { 
             call_name: name, 
             arg_count: arg_count, 
-            callback: Box::new(handler)
+            concurrency: Concurrency::Unlimited,
+            path_arguments: Vec::new(),
+            cache_ttl: None,
+            callback: callback
         });
         return self;
     }
 
+    /// Registers a handler which returns a boolean. The result is sent to the frontend as a 
+    /// real JSON boolean, so that the response's `message` parses as a `boolean` rather than
+    /// the string `"true"` or `"false"` (unless `with_string_booleans()` is used).
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register_bool("is_installed", Some(0), |context| {
+    ///     Ok(Path::new("sd:/ultimate/mods/hdr").exists())
+    /// })
+    /// ```
+    pub fn register_bool<S: ToString>(
+        &mut self, request_name: S, 
+        arg_count: Option<usize>, 
+        handler: impl Fn(&mut MessageContext) -> Result<bool, String> + 'static) -> &mut Self {
+        return self.insert_handler(request_name.to_string(), arg_count, Callback::Boolean(Box::new(handler)));
+    }
+
+    /// Makes boolean handlers (see `register_bool()`) respond with the strings `"true"` and 
+    /// `"false"` rather than JSON booleans, for compatibility with frontends which compare
+    /// the response against those strings.
+    pub fn with_string_booleans(&mut self) -> &mut Self {
+        self.config.string_booleans = true;
+        return self;
+    }
+
+    /// Makes responses of at least `threshold` bytes be gzipped and base64-encoded before 
+    /// being split into chunks, which greatly reduces the number of chunks needed for large,
+    /// repetitive responses (such as `list_all_files`). Such responses are marked with 
+    /// `"encoding": "gzip"`, and the frontend must decode the joined message (see the README).
+    /// Smaller responses, and those written by streaming handlers, are sent uncompressed.
+    pub fn with_response_compression(&mut self, threshold: usize) -> &mut Self {
+        self.config.compression_threshold = Some(threshold);
+        return self;
+    }
+
+    /// Registers a handler which writes its response incrementally, rather than returning
+    /// it as a `String`. This is useful for large responses, since the response is sent to
+    /// the frontend in chunks as it is written, instead of being built in memory first.
+    /// For small responses, prefer `register()`.
+    /// 
+    /// # Arguments
+    /// * `request_name` - the name of the request to listen for
+    /// * `arg_count` - an optional number of arguments to expect, as with `register()`.
+    /// * `handler` - this is a closure or function, which takes a `MessageContext` and a
+    ///   `ResponseWriter`, and must return `Result<(), String>`. On `Ok`, whatever was written
+    ///   to the `ResponseWriter` is sent to the frontend as an `accept()` on the original 
+    ///   `Promise`. On `Err`, the error is sent as a `reject()` instead. Note that parts of the
+    ///   response may have already been sent if the handler fails after writing a lot of data.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register_streaming("big_json", Some(0), |context, writer| {
+    ///     serde_json::to_writer(writer, &build_big_structure()).map_err(|e| e.to_string())
+    /// })
+    /// ```
+    pub fn register_streaming<S: ToString>(
+        &mut self, request_name: S, 
+        arg_count: Option<usize>, 
+        handler: impl Fn(&mut MessageContext, &mut ResponseWriter) -> Result<(), String> + 'static) -> &mut Self {
+        return self.insert_handler(request_name.to_string(), arg_count, Callback::Streaming(Box::new(handler)));
+    }
+
+    /// Registers a `RequestHandler` implementation for requests with the given name. 
+    /// This behaves exactly like `register()`, taking the expected argument count from
+    /// the handler's `arg_count()`.
+    pub fn register_handler<S: ToString>(&mut self, request_name: S, handler: Box<dyn RequestHandler>) -> &mut Self {
+        log::debug!("registering {} for {}", handler.name(), request_name.to_string());
+        let arg_count = handler.arg_count();
+        return self.register(request_name, arg_count, move |context| handler.handle(context));
+    }
+
+    /// Sets how the handler registered under the given name may run alongside other
+    /// requests. By default, handlers are `Concurrency::Unlimited`. Handlers which must
+    /// never overlap (such as two writes to the same config file) should be marked 
+    /// `Concurrency::Limit(1)`, or `Concurrency::Exclusive` if they must not run 
+    /// alongside any other handler at all. The dispatcher waits until the handler's
+    /// limit allows it to run before calling it.
+    /// 
+    /// Note that requests are currently dispatched one at a time, so these limits only
+    /// take effect once requests are handled concurrently.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register_defaults()
+    ///     .set_concurrency("write_file", Concurrency::Limit(1));
+    /// ```
+    pub fn set_concurrency<S: ToString>(&mut self, request_name: S, concurrency: Concurrency) -> &mut Self {
+        match self.handlers.get_mut(&request_name.to_string()) {
+            Some(handler) => handler.concurrency = concurrency,
+            None => log::warn!("Cannot set concurrency, no handler was registered for {}", request_name.to_string())
+        }
+        return self;
+    }
+
+    /// Caches the successful responses of the given handler for `ttl`, so that repeated 
+    /// requests with identical arguments within that time are answered from the cache, 
+    /// without calling the handler again. This suits expensive reads, such as `list_all_files`
+    /// of a large directory. The frontend can clear cached responses with `clear_cache`.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register_defaults()
+    ///     .set_cache_ttl("list_all_files", Duration::from_secs(30));
+    /// ```
+    pub fn set_cache_ttl<S: ToString>(&mut self, request_name: S, ttl: Duration) -> &mut Self {
+        match self.handlers.get_mut(&request_name.to_string()) {
+            Some(handler) => handler.cache_ttl = Some(ttl),
+            None => log::warn!("Cannot set cache ttl, no handler was registered for {}", request_name.to_string())
+        }
+        return self;
+    }
+
+    /// Marks which arguments of the given handler are paths, by index, so that they are
+    /// checked against the sandbox (see `with_sandbox()`) before the handler is called.
+    /// The default handlers' path arguments are marked by `register_defaults()`.
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register("copy_mod", Some(2), |context| { ... })
+    ///     .set_path_arguments("copy_mod", &[0, 1]);
+    /// ```
+    pub fn set_path_arguments<S: ToString>(&mut self, request_name: S, indices: &[usize]) -> &mut Self {
+        let requirements: Vec<(usize, PathKind)> = indices.iter().map(|index| (*index, PathKind::Any)).collect();
+        return self.set_path_requirements(request_name, &requirements);
+    }
+
+    /// Like `set_path_arguments()`, but also gives what each path argument must refer to 
+    /// (see `PathKind`), which is checked before the handler is called in safe mode 
+    /// (see `with_safe_mode()`).
+    /// 
+    /// Example:
+    /// ```
+    /// engine.register("install_mod", Some(2), |context| { ... })
+    ///     .set_path_requirements("install_mod", &[(0, PathKind::File), (1, PathKind::Dir)]);
+    /// ```
+    pub fn set_path_requirements<S: ToString>(&mut self, request_name: S, requirements: &[(usize, PathKind)]) -> &mut Self {
+        match self.handlers.get_mut(&request_name.to_string()) {
+            Some(handler) => {
+                handler.path_arguments = requirements.to_vec();
+                if let Some(schema) = self.state.handler_schemas.get_mut(&handler.call_name) {
+                    schema.path_arguments = requirements.iter()
+                        .map(|(index, kind)| PathArgumentSchema { index: *index, kind: *kind })
+                        .collect();
+                }
+            },
+            None => log::warn!("Cannot set path arguments, no handler was registered for {}", request_name.to_string())
+        }
+        return self;
+    }
+
     /// Registers the "default" handlers for some common functionality. 
     /// This aligns with the `nx-request-api` NPM package's DefaultMessenger.
     /// Default calls:
     /// * `ping` 
     ///     - returns ok if the backend responded to the request
+    /// * `capabilities`
+    ///     - returns the crate version, every registered handler with its argument count, and the enabled features (such as
+    ///       `zip`, `sha256` or `async`, depending on the platform, the configuration and the registered handlers)
+    /// * `get_config`
+    ///     - returns the engine's current settings as JSON, such as its sandbox, space reserve and argument size limits, 
+    ///       with the defaults filled in for settings which weren't configured
+    /// * `get_schema`
+    ///     - returns a JSON description of every registered handler (its argument count, whether it responds with a
+    ///       string, a boolean or a stream, and which arguments are paths), for generating typed frontend bindings
+    /// * `support_bundle`
+    ///     - gathers the capabilities, title and memory info, free SD space, engine configuration, recent errors, slowest
+    ///       operations and last shutdown into one JSON document for bug reports, with urls' query strings redacted.
+    ///       If a path is given, the document is written there and the path is returned instead
     /// * `read_file` 
     ///     - returns the file's contents as a string
+    /// * `read_file_full`
+    ///     - returns the file's contents (as text, or base64 if it is binary) along with its size, modified time,
+    ///       and md5. Only the first 8 MiB of larger files is returned, marked as `partial`
+    /// * `read_file_if_changed`
+    ///     - returns the file's contents, or `not_modified` if its md5 (or sha256) still matches the given hash
+    /// * `read_line`
+    ///     - returns the given line (numbered from 1) of the given file, without reading the whole file
+    /// * `read_file_encoded`
+    ///     - returns the file's contents as a string, decoded from the given encoding (such as `utf-16le` or `shift_jis`)
+    /// * `read_file_no_bom`
+    ///     - returns the file's contents without any leading byte order mark, decoded as the UTF-8, UTF-16LE or UTF-16BE
+    ///       encoding which the mark indicates (UTF-8 if there is none), as JSON along with the detected encoding
     /// * `download_file` 
-    ///     - downloads the given file to the given location
+    ///     - downloads the given file to the given location (via a `.part` file, which is removed
+    ///       if the download fails, unless `with_partial_downloads_kept()` is used). An optional third argument gives the
+    ///       expected size in bytes (or empty), which is used for progress when the server doesn't report the size. 
+    ///       An optional fourth argument (`md5` or `sha256`, or empty) hashes the file, returning the hash in the response, 
+    ///       and an optional fifth argument fails the download if the hash doesn't match it. Since the download is only 
+    ///       available as the file being written, hashing reads the whole file back, as it is written where possible.
+    /// * `download_and_verify`
+    ///     - downloads the given file to the given location, and checks it against the md5 or sha256 in the checksum file
+    ///       at the given url (such as `md5sum` output), removing it if they differ. Returns the outcome as JSON,
+    ///       or rejects with it, naming the step which failed
     /// * `delete_file` 
     ///     - deletes the given file
     /// * `write_file` 
     ///     - writes the given string to the given file location
+    /// * `write_file_sync`
+    ///     - like `write_file`, but waits until the data is flushed to the SD card before returning, 
+    ///       so that it survives an abrupt power off. This is slower, so use it only for critical files.
+    /// * `write_file_encoded`
+    ///     - writes the given string to the given file location, encoded in the given encoding
+    /// * `patch_bytes`
+    ///     - writes the given base64 bytes into the given file at the given byte offset, without rewriting the rest of it.
+    ///       Fails if the bytes would extend past the end of the file, unless the optional fourth argument is `true`
+    /// * `allocate_file`
+    ///     - creates a new file of the given size in bytes (sparse where supported), reserving space for a download 
+    ///       up front. Fails if there is not enough space.
+    /// * `trim_file`
+    ///     - if the given file is larger than the given number of bytes, rewrites it keeping only its last bytes, 
+    ///       starting at a whole line unless the optional third argument is `false`. Returns the number of bytes removed.
+    /// * `format_json`
+    ///     - rewrites the given JSON file either `pretty` printed or `minify`'d, keeping the order of keys
+    /// * `replace_in_file`
+    ///     - replaces text in the given file, optionally in `first` or `regex` mode rather than `all`,
+    ///       and returns the number of replacements made
+    /// * `benchmark_io`
+    ///     - writes a temporary file of the given size (in bytes, up to 1 GiB) and reads it back, returning the write and
+    ///       read speeds (in MB/s) as JSON, to diagnose slow SD cards. The file is removed afterwards
     /// * `get_md5`
     ///     - returns the md5 checksum of the given file
+    /// * `block_hashes`
+    ///     - returns a JSON array of the md5 checksums of each fixed-size block of the given file (the last may be shorter),
+    ///       for comparing against a remote manifest to download only the blocks which changed
+    /// * `export_manifest`
+    ///     - writes a JSON manifest of every file under the given directory (with sizes and md5s) to the given path,
+    ///       and returns the number of files
+    /// * `verify_manifest`
+    ///     - compares the given directory against the given manifest, returning the added, removed, and modified files
+    /// * `dir_hash`
+    ///     - returns a combined md5 of every file in the given directory, recursively, only rehashing files
+    ///       which changed since the last call (see `with_hash_cache_file()`)
+    /// * `dir_stats`
+    ///     - returns the total size, file and directory counts, largest files (10, or the optional second argument), and bytes 
+    ///       used by each file extension in the given directory, along with how many unreadable entries were skipped
+    /// * `find_duplicates`
+    ///     - returns the groups of identical files in the given directory, recursively, as a JSON array of each group's
+    ///       md5, size and paths (largest first). Only files which share their size are hashed
+    /// * `diff_files`
+    ///     - returns a line-based diff between the two given text files
+    /// * `apply_patch`
+    ///     - applies the given unified diff to the given file, leaving it untouched if the patch does not apply cleanly
     /// * `unzip`
     ///     - unzips the given file as to the given location
+    /// * `can_install`
+    ///     - returns the space needed to extract the given zip (approximating cluster overhead), the free space at
+    ///       the given destination, and whether it fits. Only local zip files are supported: URLs are rejected, since 
+    ///       the size of a remote archive can't be read without downloading it
+    /// * `check_zip_safety`
+    ///     - inspects the given zip file for zip bombs and path traversal without extracting it, returning a JSON report of
+    ///       whether it is `safe`. Optional arguments set the maximum total uncompressed size in bytes (4 GiB by default),
+    ///       compression ratio of any entry of at least 1 MiB (100 by default), and entry count (50000 by default)
+    /// * `read_zip_entry`
+    ///     - returns the contents of the named entry of the given zip file as a string, or as base64 if the
+    ///       optional third argument is `true`, without extracting it
+    /// * `read_zip_entries`
+    ///     - returns a JSON object of the name and base64 contents of each entry of the given zip file matching the given
+    ///       glob (such as `**/*.toml`), without extracting them. Rejects matches totalling more than 8 MiB
     /// * `file_exists`
     ///     - returns whether the given path exists and is a file
     /// * `dir_exists`
     ///     - returns whether the given path exists and is a directory
+    /// * `exists_many`
+    ///     - takes a JSON array of paths, and returns a JSON object mapping each path to `file`, `dir` or `none`
+    /// * `common_base`
+    ///     - takes a JSON array of paths, and returns the deepest directory which all of them are within (files count as 
+    ///       being within their parent directory), or an empty string if there is none
+    /// * `is_locked`
+    ///     - returns whether the given file appears to be in use, either by another handler updating it or by being open 
+    ///       elsewhere. This is best-effort, since not every platform refuses to open a file which is already open.
+    /// * `same_file`
+    ///     - returns whether the two given existing paths refer to the same underlying file (such as through a hardlink
+    ///       or symlink), so that a file is never copied onto itself
     /// * `list_all_files`
-    ///     - returns a tree structure of the given directory, recursively
+    ///     - returns a tree structure of the given directory, recursively, including file sizes and per-directory totals
+    /// * `list_all_paths`
+    ///     - returns a flat list of every file path under the given directory, recursively, optionally filtered by a glob
+    /// * `list_all_text`
+    ///     - returns every file path under the given directory as newline-delimited text, optionally filtered
+    ///       by a glob and limited to a depth, or as an indented tree (including directories) if `indent` is set
+    /// * `find_files`
+    ///     - searches the given directory for files whose names match a glob, optionally only those containing
+    ///       the given text, and returns one page of the matching paths
     /// * `list_dir`
     ///     - returns a list of the files and directories in the given path (non recursive)
+    /// * `list_subdirs`
+    ///     - returns a JSON array of the directories (not files) in the given path, relative to it. If the optional second 
+    ///       argument is `true`, subdirectories are listed recursively, up to the depth given by the optional third argument
+    /// * `stream_list_dir`
+    ///     - pushes the files and directories in the given path (non recursive) to the frontend as they are found, as
+    ///       `list_dir_batch` events carrying the request's id, in batches of an optional size (100 by default).
+    ///       Returns the number of entries once every batch has been sent
+    /// * `follow_file` / `stop_follow`
+    ///     - follows the given file like `tail -f`, returning a follow id: whatever is appended to it is pushed as `follow`
+    ///       events (with the follow id) while the engine is idle, until `stop_follow` is called with the id. If the optional
+    ///       second argument is `true`, the existing contents are sent first. A truncated or rotated file is read from its start
+    /// * `open_dir` / `next_dir_entries` / `close_dir`
+    ///     - reads a huge directory a batch at a time: `open_dir` returns a handle for the given directory, `next_dir_entries`
+    ///       returns up to the given number of further entries for a handle (closing it once `done`), and `close_dir` closes
+    ///       a handle early. Handles left unused for five minutes are closed
+    /// * `list_romfs` / `read_romfs`
+    ///     - lists a directory of, or reads a text file from, the read-only romfs (paths are relative to `rom:/`),
+    ///       rejecting with `unsupported` where no romfs is mounted
     /// * `get_request`
-    ///     - performs a GET request (using `smashnet`) and returns the body as a string
+    ///     - performs a GET request (using `smashnet`) and returns the body as a string. If the optional second
+    ///       argument is `true`, a gzip-encoded body is decompressed first
+    /// * `get_requests`
+    ///     - performs a GET request for each url in the given JSON array, returning each url's result.
+    ///       The requests run in parallel, up to the download concurrency
+    /// * `get_download_concurrency` / `set_download_concurrency`
+    ///     - returns, or changes, how many downloads batch operations run at once (1 by default)
+    /// * `check_connectivity`
+    ///     - performs a GET request to the given url (or the switch's connection test, if none is given),
+    ///       and returns whether it was reachable along with the latency
     /// * `exit_session`
     ///     - signals the engine to shutdown and the session to close, unblocking `start()`
+    ///       (see `with_exit_acknowledgement()` to acknowledge the request before closing)
+    /// * `get_shutdown_status`
+    ///     - returns how the last shutdown went (`clean`, `timed_out`, or `errored`), as JSON, or `null` if there was none. 
+    ///       The status is kept in the working directory (see `with_config_dir()`), so it survives the plugin being reloaded
     /// * `exit_application`
-    ///     - closes the application entirely (you will return to the home menu)
+    ///     - closes the application entirely (you will return to the home menu), or rejects with
+    ///       `unsupported on this platform` when not running on the switch
+    /// * `log`
+    ///     - logs the given message, at an optional level of `info` (default), `warn`, or `error`
+    ///       (and writes it to a rotating log file, see `with_log_file()`)
+    /// * `log_json`
+    ///     - appends the given JSON object, with a `backend_timestamp` (seconds since the unix epoch) added, as a single
+    ///       line of `frontend.jsonl` in the log directory, which is rotated like `frontend.log` (see `with_log_file()`)
+    /// * `rename`
+    ///     - renames the given file or directory. A rename which only changes the casing of the name is done
+    ///       in two steps via an intermediate name, since case-insensitive filesystems may otherwise ignore it
+    /// * `split_file`
+    ///     - splits the given file into numbered parts of the given size in bytes, returning the part paths
+    ///       and the md5 of the whole file
+    /// * `join_files`
+    ///     - joins the parts in the given JSON array into the given file, verifying it against an optional md5,
+    ///       and returns the joined file's md5
+    /// * `make_temp_file` / `make_temp_dir`
+    ///     - creates a uniquely named, empty file or directory (with an optional name prefix) in the temp
+    ///       directory (see `with_temp_dir()`), and returns its path. These are removed when the engine shuts down
+    /// * `relative_path`
+    ///     - returns the path of the second location relative to the first (a directory), after normalizing both,
+    ///       or the second location's absolute path if they are on different roots
+    /// * `can_create_files`
+    ///     - estimates whether the given number of files (with an optional typical name length) can be created
+    ///       in the given directory, under FAT32's directory entry limit, returning the answer with a reason
+    /// * `create_hardlink`
+    ///     - creates a hard link to the given file, rejecting with `unsupported` where the filesystem does not allow it
+    /// * `compress_file` / `decompress_file`
+    ///     - compresses (or decompresses) the given file into the given output file with the given algorithm (`gzip`
+    ///       or `zstd`), returning the input and output sizes and the compression ratio as JSON
+    /// * `merge_dir`
+    ///     - copies every file from the source directory into the destination, resolving conflicts by
+    ///       the given policy (`overwrite`, `skip`, or `newer`), and returns the counts of each outcome. If the optional
+    ///       fourth argument is `true`, copied files keep their source's modified time, rather than the time of the copy
+    /// * `trash_file`
+    ///     - moves the given file into the trash directory (see `with_trash_dir()`), or deletes it permanently
+    ///       with a warning if there is none
+    /// * `archive_file`
+    ///     - moves the given file into a subdirectory for the current date (such as `2024-06-01`) of the configured archive 
+    ///       directory (see `with_archive_dir()`), adding a counter to its name if needed, and returns its archived path
+    /// * `restore_trashed`
+    ///     - moves a trashed file back to its original location
+    /// * `empty_trash`
+    ///     - permanently deletes everything in the trash directory
+    /// * `validate_path`
+    ///     - returns whether the given path is permitted by the sandbox (see `with_sandbox()`), and its normalized form
+    /// * `realpath`
+    ///     - returns the canonical form of the given existing path, with any symlinks resolved
+    /// * `start_job`
+    ///     - starts a background job and returns its id immediately: `download` (url, location), `extract`
+    ///       (zip, directory), or `hash` (path, algorithm). Finished jobs are remembered for `job_status` 
+    ///       until 16 newer jobs have finished
+    /// * `cancel_job`
+    ///     - cancels the given running background job at its next checkpoint, removing any files it created
+    ///       (downloads are cancelled once the transfer completes, since it can't be interrupted)
+    /// * `job_status`
+    ///     - returns the state (`running`, `done`, `failed` or `cancelled`), phase, and progress of the given background job,
+    ///       along with its result or error once finished
+    /// * `get_config_dir` / `set_config_dir`
+    ///     - returns, or changes, the backend's working directory (see `with_config_dir()`), creating it
+    ///       if needed. A new directory must be writable, and is remembered across sessions. Unless configured
+    ///       otherwise, the trash is kept in the chosen directory (existing files are not moved when it changes)
+    /// * `clear_cache`
+    ///     - clears the cached responses of the given handler, or of every handler (see `set_cache_ttl()`),
+    ///       and returns how many were cleared
+    /// * `subscribe` / `unsubscribe`
+    ///     - starts, or stops, pushing events of the given topic to the frontend (see `MessageContext::publish()`).
+    ///       `download_file` publishes `download_complete`, with the downloaded file's location
+    /// * `get_recent_errors`
+    ///     - returns the most recent errors returned to the frontend (see `with_error_history()`)
+    /// * `get_result`
+    ///     - returns the result of the earlier request with the given id again (rejecting if that request failed), without
+    ///       running its handler again (see `with_result_history()`). Boolean results are returned as `true` or `false`
+    /// * `get_call_history` / `replay_call`
+    ///     - returns the most recent requests from the frontend, or sends the recorded request with the given id again, 
+    ///       returning the id which the replay will respond with (see `with_call_history()`)
+    /// * `slow_operations`
+    ///     - returns the slowest handler invocations so far (see `with_slow_operation_tracking()`)
+    /// * `clipboard_get` / `clipboard_set`
+    ///     - reads or writes the system clipboard, rejecting with `unsupported` where there is none (currently always)
+    /// * `engine_busy`
+    ///     - returns whether the engine is running another request or a background job, such as to disable actions in the
+    ///       meantime (see also `RequestEngine::is_busy()`)
+    /// * `memory_info`
+    ///     - returns the application's current memory usage and availability
+    /// * `filesystem_info`
+    ///     - returns the type of filesystem the given path is on, whether it is read-only, its free space, and its
+    ///       largest possible file (such as FAT32's 4GB limit), each `null` where the platform can't report it. The SD card's 
+    ///       type can't be detected, so it is assumed to have FAT32's limits (and `limits_assumed` is `true`)
+    /// * `title_info`
+    ///     - returns the running title's id and display version (or `unknown` where unavailable), and this crate's version
+    /// * `normalize_line_endings`
+    ///     - rewrites the given file's line endings to `lf` or `crlf`, returning the number of lines changed
     pub fn register_defaults(&mut self) -> &mut Self {
         default_handlers::register_defaults(self);
         return self;
@@ -118,11 +836,45 @@ impl RequestEngine {
     /// Start the request engine. This will block and internally loop until `shutdown()` 
     /// has been called by a handler (such as with `exitSession()` in the 
     /// `DefaultMessenger`, or via `context.shutdown()` in a registered custom handler);
+    /// or until a response can't be sent because the web page has closed unexpectedly.
     pub fn start(&mut self) {
+        // use the working directory chosen in a previous session, if any
+        if self.state.config_dir.is_none() {
+            self.state.config_dir = config::load_config_dir();
+        }
+        if let Some(max_age) = self.config.temp_cleanup_age_secs {
+            remove_stale_temp_files(&self.config.temp_dir(), max_age);
+        }
+        reset_session_closed();
+        set_send_timeout(Duration::from_secs(self.config.send_timeout()));
+        let mut last_probe = Instant::now();
         while !self.is_exit {
+            // the web page closed unexpectedly, so there is nobody left to handle requests for
+            if is_session_closed() {
+                log::warn!("The session can no longer be sent to, so the engine is stopping");
+                let config_dir = self.state.config_dir.clone().unwrap_or_else(|| self.config.config_dir());
+                self.state.record_shutdown(&config_dir, ShutdownStatus {
+                    outcome: "errored".to_string(),
+                    duration_ms: 0,
+                    error: Some("the session closed unexpectedly".to_string()),
+                    timestamp: unix_timestamp()
+                });
+                self.is_exit = true;
+                break;
+            }
             println!("listening");
-            // block until we get a message from the frontend
-            let msg = self.session.recv();
+            // handle any messages which arrived during the last request first,
+            // otherwise block until we get a message from the frontend
+            let msg = match self.state.pending_messages.get_mut().pop_front() {
+                Some(msg) => msg,
+                None => match self.next_message(&mut last_probe) {
+                    Some(msg) => msg,
+                    None => continue
+                }
+            };
+            if let Some(ref hook) = self.raw_message_hook {
+                hook(&msg);
+            }
             let message = match serde_json::from_str::<Message>(&msg) {
                 Ok(message) => {
                     message
@@ -138,11 +890,36 @@ impl RequestEngine {
             };
             let call_name = message.call_name.clone();
 
+            // remember the request for get_call_history, if enabled
+            if self.config.call_history_size > 0 {
+                self.state.record_call(&message.id, &call_name, &message.arguments, self.config.call_history_size);
+            }
+
+            // reject oversized arguments before they are handed to any handler
+            if let Err(error) = self.config.check_argument_sizes(&message.arguments) {
+                let mut ctx = MessageContext::build(message, &self.session, &self.config, &mut self.state);
+                let error = serde_json::to_string(&error).unwrap_or(error.error);
+                ctx.return_error(&error);
+                continue;
+            }
+
+            // cancellations are only meaningful while the target request is being 
+            // handled (see `MessageContext::is_cancelled()`), so this one is too late.
+            if call_name == CANCEL_CALL_NAME {
+                let mut ctx = MessageContext::build(message, &self.session, &self.config, &mut self.state);
+                ctx.return_error("no request with the given id is in progress");
+                continue;
+            }
+
             // try to handle the message
             match self.handlers.contains_key(&call_name) {
                 true => {
                     println!("handling {}", call_name);
-                    let mut ctx = MessageContext::build(message, &self.session);
+                    let mut ctx = MessageContext::build(message, &self.session, &self.config, &mut self.state);
+                    if !self.config.is_call_permitted(&call_name) {
+                        ctx.return_error(&format!("{} is disabled", call_name));
+                        continue;
+                    }
                     // if an expected arg count was specified in the handler,
                     // we must ensure that this is reality. If not, respond with an error.
                     let handler = self.handlers.get(&call_name).unwrap();
@@ -165,16 +942,92 @@ impl RequestEngine {
                         }
                     }
 
-                    // run the registered callback
-                    let result = (handler.callback)(&mut ctx);
+                    // confine path arguments to the sandbox, if there is one
+                    if !self.config.sandbox_roots.is_empty() {
+                        if let Err(error) = sandbox_arguments(&self.config, &handler.path_arguments, &mut ctx.arguments) {
+                            ctx.return_error(&error);
+                            continue;
+                        }
+                    }
+
+                    // check that path arguments refer to what they must, in safe mode
+                    if self.config.safe_mode {
+                        if let Err(error) = check_path_kinds(&handler.path_arguments, &ctx.arguments) {
+                            ctx.return_error(&error);
+                            continue;
+                        }
+                    }
+
+                    // answer from the cache, if the handler is cached and the response is fresh
+                    let cache_key = ctx.arguments.clone().unwrap_or_default();
+                    if handler.cache_ttl.is_some() {
+                        if let Some(cached) = ctx.state.cached_response(&call_name, &cache_key) {
+                            match cached {
+                                CachedResponse::Text(res) => ctx.return_ok(&res),
+                                CachedResponse::Bool(res) => ctx.return_bool(res),
+                                CachedResponse::Streamed(res) => {
+                                    let mut writer = ResponseWriter::new(ctx.id.clone(), ctx.session);
+                                    writer.write_str(&res);
+                                    writer.finish();
+                                }
+                            }
+                            continue;
+                        }
+                    }
+
+                    // run the registered callback, once its concurrency limit allows
+                    let permit = self.limiter.acquire(&call_name, handler.concurrency);
+                    let mut writer = ResponseWriter::new(ctx.id.clone(), ctx.session);
+                    let keeps_result = self.config.result_history_size > 0 && call_name != "get_result";
+                    if handler.cache_ttl.is_some() || keeps_result {
+                        writer.record();
+                    }
+                    let start_time = std::time::Instant::now();
+                    let busy = ctx.state.busy.enter();
+                    let result = match handler.callback {
+                        Callback::Simple(ref callback) => callback(&mut ctx).map(Response::Text),
+                        Callback::Boolean(ref callback) => callback(&mut ctx).map(Response::Bool),
+                        Callback::Streaming(ref callback) => callback(&mut ctx, &mut writer).map(|_| Response::Streamed)
+                    };
+                    drop(busy);
+                    drop(permit);
+
+                    // remember slow invocations for slow_operations, if enabled
+                    if self.config.slow_operation_count > 0 {
+                        let record = OperationRecord {
+                            call_name: call_name.clone(),
+                            arguments: sanitize_arguments(&ctx.arguments),
+                            duration_ms: start_time.elapsed().as_millis() as u64,
+                            timestamp: unix_timestamp()
+                        };
+                        ctx.state.record_operation(record, self.config.slow_operation_count);
+                    }
+
+                    let response = match &result {
+                        Ok(Response::Text(res)) => Ok(CachedResponse::Text(res.clone())),
+                        Ok(Response::Bool(res)) => Ok(CachedResponse::Bool(*res)),
+                        Ok(Response::Streamed) => Ok(CachedResponse::Streamed(writer.take_recording().unwrap_or_default())),
+                        Err(err) => Err(err.clone())
+                    };
+                    if let (Some(ttl), Ok(cached)) = (handler.cache_ttl, &response) {
+                        ctx.state.cache_response(&call_name, cache_key, cached.clone(), ttl);
+                    }
+                    // keep the result for get_result, if enabled
+                    if keeps_result {
+                        let ttl = Duration::from_secs(self.config.result_history_ttl_secs);
+                        ctx.state.store_result(&ctx.id, response, self.config.result_history_size, ttl);
+                    }
 
                     // if the callback signaled a shutdown, then 
                     // shutdown the engine and session
                     if ctx.is_shutdown() {
-                        return;
+                        break;
                     } else {
                         match result {
-                            Ok(res) => ctx.return_ok(&res),
+                            Ok(Response::Text(res)) => ctx.return_ok(&res),
+                            Ok(Response::Bool(res)) => ctx.return_bool(res),
+                            // the response was streamed, so just send what remains
+                            Ok(Response::Streamed) => writer.finish(),
                             Err(err) => ctx.return_error(&err)
                         }
                     }
@@ -182,13 +1035,108 @@ impl RequestEngine {
                 false => println!("No handler was registered for {}", &message.call_name)
             }
         }
+        self.state.remove_temp_paths();
+        self.state.subscriptions.clear();
+        self.state.dir_cursors.clear();
+        self.state.follows.clear();
+    }
+
+    /// waits for the next message from the frontend. While files are being followed (see 
+    /// `follow_file`), or the session is probed (see `with_session_probe()`), this polls 
+    /// instead of blocking, sending what was appended to the files and checking that the 
+    /// session is still open in the meantime. Returns `None` if the session closed while polling.
+    fn next_message(&mut self, last_probe: &mut Instant) -> Option<String> {
+        let probe_interval = self.config.session_probe_secs.map(Duration::from_secs);
+        loop {
+            if self.state.follows.is_empty() && probe_interval.is_none() {
+                return Some(self.session.recv());
+            }
+            if let Some(msg) = self.session.try_recv() {
+                *last_probe = Instant::now();
+                return Some(msg);
+            }
+            default_handlers::poll_follows(&self.session, &mut self.state);
+            // a closed session is only noticed when sending to it fails
+            if let Some(interval) = probe_interval {
+                if last_probe.elapsed() >= interval {
+                    send_event(&self.session, HEARTBEAT_TOPIC, "");
+                    *last_probe = Instant::now();
+                }
+            }
+            if is_session_closed() {
+                return None;
+            }
+            std::thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// how often the engine polls for messages (checking followed files in between), while idle
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// removes the entries of the given temp directory which were 
+/// last modified more than `max_age_secs` ago.
+fn remove_stale_temp_files(temp_dir: &str, max_age_secs: u64) {
+    let entries = match std::fs::read_dir(temp_dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    let max_age = Duration::from_secs(max_age_secs);
+    for entry in entries.flatten() {
+        let is_stale = entry.metadata().ok()
+            .and_then(|md| md.modified().ok())
+            .and_then(|modified| modified.elapsed().ok())
+            .map(|age| age > max_age)
+            .unwrap_or(false);
+        if !is_stale {
+            continue;
+        }
+        let path = entry.path();
+        let result = match path.is_dir() {
+            true => std::fs::remove_dir_all(&path),
+            false => std::fs::remove_file(&path)
+        };
+        match result {
+            Ok(_) => log::info!("Removed stale temp file {}", path.display()),
+            Err(e) => log::warn!("Could not remove stale temp file {}: {:?}", path.display(), e)
+        }
+    }
+}
+
+/// checks the given path arguments against the sandbox, replacing 
+/// each with its normalized form if it is permitted.
+fn sandbox_arguments(config: &EngineConfig, requirements: &[(usize, PathKind)], arguments: &mut Option<Vec<String>>) -> Result<(), String> {
+    if let Some(args) = arguments {
+        for (index, _) in requirements {
+            if let Some(arg) = args.get_mut(*index) {
+                *arg = sandbox::check_path(config, arg)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// checks that each path argument refers to what it must, returning
+/// a JSON `PathError` for the first which does not.
+fn check_path_kinds(requirements: &[(usize, PathKind)], arguments: &Option<Vec<String>>) -> Result<(), String> {
+    if let Some(args) = arguments {
+        for (index, kind) in requirements {
+            if let Some(arg) = args.get(*index) {
+                if let Err(error) = sandbox::check_kind(arg, *index, *kind) {
+                    return Err(serde_json::to_string(&error).unwrap_or(error.error));
+                }
+            }
+        }
     }
+    Ok(())
 }
 
 #[cfg(test)]
+// `can_construct` fabricates a session from a null pointer, since a real one needs the switch
+#[allow(deref_nullptr)]
 mod tests {
     use skyline_web::WebSession;
-    use crate::{RequestEngine, response::Progress};
+    use crate::{RequestEngine, Progress};
     
 
     #[test]
@@ -199,6 +1147,9 @@ mod tests {
             .register(
                 "test",
                 None,
-                |context| context.send_progress(Progress{title: "Progress".to_owned(), info: "progress!".to_owned(), progress: 50}));
+                |context| {
+                    context.send_progress(Progress{title: "Progress".to_owned(), info: "progress!".to_owned(), progress: 0.5});
+                    Ok("done".to_owned())
+                });
     }
 }
\ No newline at end of file
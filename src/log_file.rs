@@ -0,0 +1,66 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// the path of the `index`th backup of the given log file, e.g. `frontend.log.1`
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// shifts each backup of the log file up by one, discarding the oldest,
+/// and moves the current log file into the first backup slot.
+fn rotate(path: &Path, backups: usize) -> std::io::Result<()> {
+    if backups == 0 {
+        return fs::remove_file(path);
+    }
+    let oldest = backup_path(path, backups);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..backups).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, backup_path(path, 1))
+}
+
+/// appends a line to the given log file, first rotating it if the line
+/// would grow the file beyond `max_size` bytes. At most `backups` rotated
+/// files are kept alongside the current one.
+pub(crate) fn append_line(path: &Path, line: &str, max_size: u64, backups: usize) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let current_size = fs::metadata(path).map(|md| md.len()).unwrap_or(0);
+    if current_size > 0 && current_size + line.len() as u64 + 1 > max_size {
+        rotate(path, backups)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // write the line in one call, so that it lands whole
+    file.write_all(format!("{}\n", line).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_line, backup_path};
+    use std::fs;
+
+    #[test]
+    fn rotates_and_caps_backups() {
+        let dir = std::env::temp_dir().join("nx-request-handler-log-test");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("frontend.log");
+        for index in 0..5 {
+            append_line(&path, &format!("line {}", index), 10, 2).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line 4\n");
+        assert_eq!(fs::read_to_string(backup_path(&path, 1)).unwrap(), "line 3\n");
+        assert_eq!(fs::read_to_string(backup_path(&path, 2)).unwrap(), "line 2\n");
+        assert!(!backup_path(&path, 3).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
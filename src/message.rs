@@ -1,10 +1,21 @@
 use serde::{Serialize, Deserialize};
 use skyline_web::WebSession;
 use std::fmt;
+use std::cell::Cell;
+use std::io::Write;
+use base64::Engine;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use crate::response::*;
 use crate::Progress;
+use crate::concurrency::FileLock;
+use crate::config::EngineConfig;
+use crate::state::{EngineState, unix_timestamp};
 use serde_json::json;
 
+/// how long closing the session may take before the shutdown is reported as `timed_out`
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// this represents the message format that we will
 /// receive from the frontend.
 #[derive(Serialize, Deserialize)]
@@ -37,21 +48,50 @@ pub struct MessageContext<'a> {
     pub arguments: Option<Vec<String>>,
     /// the websession (USE GREAT CARE IN OPERATING ON THIS.)
     pub session: &'a WebSession,
+    /// the engine's configuration
+    pub(crate) config: &'a EngineConfig,
+    /// the engine's runtime state
+    pub(crate) state: &'a mut EngineState,
     /// whether we are signalling intent to shutdown the engine
-    is_shutdown: bool
+    is_shutdown: bool,
+    /// whether the frontend has asked for this request to be cancelled
+    cancelled: Cell<bool>
 }
 
 impl <'a>MessageContext<'a> {
     /// builds the `MessageContext` for a handler to consume.
-    pub(crate) fn build(message: Message, session: &WebSession) -> MessageContext {
-        return MessageContext { id: message.id, call_name: message.call_name, arguments: message.arguments, session: session, is_shutdown: false }
+    pub(crate) fn build(message: Message, session: &'a WebSession, config: &'a EngineConfig, state: &'a mut EngineState) -> MessageContext<'a> {
+        return MessageContext { id: message.id, call_name: message.call_name, arguments: message.arguments, session: session, config: config, state: state, is_shutdown: false, cancelled: Cell::new(false) }
     }
     /// immediately closes the session, and then signals that the engine
     /// will shutdown and unblock the `start()` thread upon completion of
     /// the current handler's operations.
     pub fn shutdown(&mut self) {
+        // `exit()` and `wait_for_exit()` don't report failures, so infer them instead
+        let already_closed = is_session_closed();
+        let start = Instant::now();
         self.session.exit();
         self.session.wait_for_exit();
+        let duration = start.elapsed();
+
+        let (outcome, error) = if already_closed {
+            ("errored", Some("the session could no longer be sent to before exiting, so the page may not have closed".to_string()))
+        } else if duration > SHUTDOWN_TIMEOUT {
+            ("timed_out", Some(format!("the session took {}ms to exit", duration.as_millis())))
+        } else {
+            ("clean", None)
+        };
+        match &error {
+            Some(error) => log::warn!("Shutdown was not clean: {}", error),
+            None => log::info!("Shutdown completed cleanly in {}ms", duration.as_millis())
+        }
+        let config_dir = self.config_dir();
+        self.state.record_shutdown(&config_dir, ShutdownStatus {
+            outcome: outcome.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            error: error,
+            timestamp: unix_timestamp()
+        });
         self.is_shutdown = true;
     }
     /// whether the engine has been signalled to shut down
@@ -61,7 +101,7 @@ impl <'a>MessageContext<'a> {
     /// sends the given `Progress` struct to the frontend, for progress 
     /// reporting of long-running operations.
     pub fn send_progress(&self, progress: Progress) {
-        self.session.send(&serde_json::to_string(&StringResponse{
+        send_message(self.session, &serde_json::to_string(&StringResponse{
             id: "progress".to_string(), 
             message: serde_json::to_string(&progress)
                 .unwrap()
@@ -76,50 +116,321 @@ impl <'a>MessageContext<'a> {
             more: false
         }).unwrap());
         //println!("sent progress: {}", progress.progress);
+        self.poll_cancellation();
+    }
+    /// pushes an event with the given data to the frontend, if it has subscribed to the 
+    /// given topic (with `subscribe`). Events are sent with the id `event`, outside of 
+    /// any request's response, so they can notify the frontend of things as they happen.
+    pub fn publish(&self, topic: &str, data: &str) {
+        if !self.state.subscriptions.contains(topic) {
+            return;
+        }
+        self.send_event(topic, data);
+    }
+    /// pushes an event with the given data to the frontend, whether or not it has subscribed
+    /// to the given topic. This is for events which the frontend asked for with the current 
+    /// request, such as the batches of `stream_list_dir`.
+    pub fn send_event(&self, topic: &str, data: &str) {
+        send_event(self.session, topic, data);
+    }
+    /// whether the frontend has sent a `cancel` request for this request. Long-running
+    /// handlers should check this periodically, and return an `Err` when it is true.
+    pub fn is_cancelled(&self) -> bool {
+        self.poll_cancellation();
+        self.cancelled.get()
+    }
+    /// checks for any requests which arrived while this one was being handled. 
+    /// `cancel` requests are handled immediately, while anything else is
+    /// queued to be handled once the current request has completed.
+    fn poll_cancellation(&self) {
+        while let Some(msg) = self.session.try_recv() {
+            match serde_json::from_str::<Message>(&msg) {
+                Ok(message) if message.call_name == CANCEL_CALL_NAME => {
+                    let target = message.arguments.as_ref().and_then(|args| args.first().cloned());
+                    if target.as_deref() == Some(self.id.as_str()) {
+                        self.cancelled.set(true);
+                        send_result(self.session, &message.id, "cancellation requested", true);
+                    } else {
+                        send_result(self.session, &message.id, "no request with the given id is in progress", false);
+                    }
+                },
+                _ => self.state.pending_messages.borrow_mut().push_back(msg)
+            }
+        }
+    }
+    /// takes the advisory lock on the given file, blocking until no other request holds it,
+    /// and releasing it when the returned `FileLock` is dropped. Handlers which read, modify 
+    /// and write back a file should hold its lock throughout, so that updates aren't lost.
+    /// 
+    /// Example:
+    /// ```
+    /// let _lock = context.lock_file("sd:/ultimate/config.json");
+    /// // read, modify and write the file
+    /// ```
+    pub fn lock_file(&self, path: &str) -> FileLock {
+        self.state.file_locks.lock(path)
+    }
+    /// the backend's working directory, where features keep their files. This is 
+    /// the directory chosen with `set_config_dir`, if any, or else the configured one.
+    pub fn config_dir(&self) -> String {
+        return match self.state.config_dir {
+            Some(ref dir) => dir.clone(),
+            None => self.config.config_dir()
+        }
+    }
+    /// the directory which `trash_file` moves files into: the configured one, or else `trash` 
+    /// in the working directory, if that was chosen with `with_config_dir()` or `set_config_dir`
+    pub fn trash_dir(&self) -> Option<String> {
+        return match (&self.config.trash_dir, &self.state.config_dir, &self.config.config_dir) {
+            (Some(dir), _, _) => Some(dir.clone()),
+            (None, None, None) => None,
+            _ => Some(format!("{}/trash", self.config_dir()))
+        }
     }
     pub(crate) fn return_bool(&self, result: bool) {
         //println!("Sending {}", result);
-        self.return_ok(result.to_string().as_str());
+        if self.config.string_booleans {
+            self.return_ok(result.to_string().as_str());
+            return;
+        }
+        send_message(self.session, &serde_json::to_string(&BooleanResponse{
+            id: self.id.clone(), ok: true, message: result, more: false
+        }).unwrap());
     }
     fn return_result(&self, orig_message: &str, is_ok: bool) {
-        let cleaned_message = orig_message
-            .replace("\r", "")
-            .replace("\0", "")
-            .replace("\\", "\\\\")
-            .replace("\n", "\\n")
-            .replace("\"", "\\\"")
-            .replace("\t", "    ");
-        let message = cleaned_message.trim();
-        let total_length = message.len();
-        let mut index = 0;
-
-        // send the data in chunks
-        while index < total_length {
-            let mut end_index = (index + CHUNK_SIZE).min(total_length);
-            let mut slice = &message[index..end_index];
-            while slice.chars().last().unwrap() == '\\' {
-                end_index = end_index + 1;
-                slice = &message[index..end_index];
-            }
-            
-            let data = serde_json::to_string(&OkOrErrorResponse{ 
-                id: self.id.clone(), ok: is_ok, message: slice.to_string(), more: (end_index < total_length)
-            }).unwrap();
-            if data.len() < 500 {
-                println!("Sending chunk:\n'{}'", data);
-            } else {
-                println!("Sending chunk of lenth: {}", data.len());
-            }
-            self.session.send(&data);
-            index = end_index;
-            //println!("Chunked send percentage: {}%", 100.0 * index as f32/total_length as f32)
+        match self.config.compression_threshold {
+            Some(threshold) if orig_message.len() >= threshold => send_compressed_result(self.session, &self.id, orig_message, is_ok),
+            _ => send_result(self.session, &self.id, orig_message, is_ok)
         }
     }
     pub(crate) fn return_ok(&self, message: &str) {
         self.return_result(message, true);
     }
-    pub(crate) fn return_error(&self, message: &str) {
+    pub(crate) fn return_error(&mut self, message: &str) {
+        // remember the error for get_recent_errors, if enabled
+        let history_size = self.config.error_history_size;
+        if history_size > 0 {
+            self.state.recent_errors.push_back(ErrorRecord { 
+                call_name: self.call_name.clone(), id: self.id.clone(), message: message.to_string(), timestamp: unix_timestamp() 
+            });
+            while self.state.recent_errors.len() > history_size {
+                self.state.recent_errors.pop_front();
+            }
+        }
         self.return_result(message, false);
     }
 }
-const CHUNK_SIZE: usize = 25000;
\ No newline at end of file
+/// the most characters sent to the frontend in a single message
+pub(crate) const CHUNK_SIZE: usize = 25000;
+
+/// the id with which events are sent, see `MessageContext::publish()`
+pub const EVENT_ID: &str = "event";
+
+/// the reserved call name with which the frontend cancels a request in progress
+pub const CANCEL_CALL_NAME: &str = "cancel";
+
+/// the topic of the events which check that the session is still open, see `with_session_probe()`
+pub const HEARTBEAT_TOPIC: &str = "heartbeat";
+
+/// how long sending a message may keep failing, in milliseconds, before the session is 
+/// considered closed. This is set from the engine's configuration when it starts.
+static SEND_TIMEOUT_MS: AtomicU64 = AtomicU64::new(crate::config::DEFAULT_SEND_TIMEOUT_SECS * 1000);
+
+/// set once sending to the session has failed, meaning that the web page has closed. Only
+/// one web session can be open at a time, so this is shared rather than per-engine.
+static SESSION_CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// whether sending to the session has failed, because the web page has closed
+pub(crate) fn is_session_closed() -> bool {
+    SESSION_CLOSED.load(Ordering::SeqCst)
+}
+
+/// forgets that a previous session was closed, for a new session
+pub(crate) fn reset_session_closed() {
+    SESSION_CLOSED.store(false, Ordering::SeqCst);
+}
+
+/// sets how long sending a message may keep failing before the session is considered closed
+pub(crate) fn set_send_timeout(timeout: Duration) {
+    SEND_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::SeqCst);
+}
+
+/// sends the given message to the frontend. Unlike `WebSession::send()`, this gives up 
+/// (and marks the session as closed) if the message can't be sent within the send timeout
+/// (see `with_send_timeout()`), rather than retrying forever. Returns whether the message was sent.
+pub(crate) fn send_message(session: &WebSession, data: &str) -> bool {
+    if is_session_closed() {
+        return false;
+    }
+    let timeout = Duration::from_millis(SEND_TIMEOUT_MS.load(Ordering::SeqCst));
+    let start = Instant::now();
+    loop {
+        if session.try_send(data) {
+            return true;
+        }
+        if start.elapsed() >= timeout {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    log::warn!("Could not send to the session, it appears to have closed.");
+    SESSION_CLOSED.store(true, Ordering::SeqCst);
+    false
+}
+
+/// pushes an event with the given data to the frontend, outside of any request's response
+pub(crate) fn send_event(session: &WebSession, topic: &str, data: &str) {
+    send_message(session, &serde_json::to_string(&EventResponse{
+        id: EVENT_ID.to_string(), topic: topic.to_string(), data: data.to_string(), more: false
+    }).unwrap());
+}
+
+/// sends the given result to the frontend as the response to the request with 
+/// the given id, split into chunks if needed.
+pub(crate) fn send_result(session: &WebSession, id: &str, orig_message: &str, is_ok: bool) {
+    let mut cleaned_message = String::with_capacity(orig_message.len());
+    for c in orig_message.chars() {
+        escape_char(c, &mut cleaned_message);
+    }
+    send_chunks(session, id, cleaned_message.trim(), is_ok, None);
+}
+
+/// sends the given result to the frontend like `send_result()`, but gzipped and 
+/// base64-encoded, which greatly reduces the number of chunks for large, repetitive
+/// responses (such as directory listings). The response is marked with the `gzip` 
+/// encoding, so that the frontend knows to decode it once all chunks have arrived.
+pub(crate) fn send_compressed_result(session: &WebSession, id: &str, orig_message: &str, is_ok: bool) {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let compressed = match encoder.write_all(orig_message.as_bytes()).and_then(|_| encoder.finish()) {
+        Ok(compressed) => compressed,
+        Err(e) => {
+            log::warn!("Could not compress response, sending it uncompressed. Error: {:?}", e);
+            return send_result(session, id, orig_message, is_ok);
+        }
+    };
+    let encoded = base64::engine::general_purpose::STANDARD.encode(compressed);
+    send_chunks(session, id, &encoded, is_ok, Some("gzip"));
+}
+
+/// sends an already-escaped message to the frontend, split into chunks.
+fn send_chunks(session: &WebSession, id: &str, message: &str, is_ok: bool, encoding: Option<&str>) {
+    let total_length = message.len();
+    let mut index = 0;
+
+    // send the data in chunks
+    while index < total_length {
+        let mut end_index = (index + CHUNK_SIZE).min(total_length);
+        let mut slice = &message[index..end_index];
+        while slice.chars().last().unwrap() == '\\' {
+            end_index = end_index + 1;
+            slice = &message[index..end_index];
+        }
+        
+        let data = serde_json::to_string(&OkOrErrorResponse{ 
+            id: id.to_string(), ok: is_ok, message: slice.to_string(), more: (end_index < total_length),
+            encoding: encoding.map(|encoding| encoding.to_string())
+        }).unwrap();
+        if data.len() < 500 {
+            println!("Sending chunk:\n'{}'", data);
+        } else {
+            println!("Sending chunk of lenth: {}", data.len());
+        }
+        if !send_message(session, &data) {
+            return;
+        }
+        index = end_index;
+        //println!("Chunked send percentage: {}%", 100.0 * index as f32/total_length as f32)
+    }
+}
+
+
+/// escapes a single character of a response message for transport,
+/// appending the result to `out`.
+fn escape_char(c: char, out: &mut String) {
+    match c {
+        '\r' | '\0' => {},
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '"' => out.push_str("\\\""),
+        '\t' => out.push_str("    "),
+        _ => out.push(c)
+    }
+}
+
+/// A writer which sends a response to the frontend incrementally, in chunks, as 
+/// it is written to. This is handed to handlers registered via `register_streaming()`,
+/// and allows large responses (such as big JSON structures, via `serde_json::to_writer()`)
+/// to be sent without first building the entire response as a `String`.
+pub struct ResponseWriter<'a> {
+    id: String,
+    session: &'a WebSession,
+    /// escaped data which has not been sent yet
+    buffer: String,
+    /// trailing bytes of an incomplete UTF-8 character
+    pending: Vec<u8>,
+    /// a copy of everything written, if recording (for caching the response)
+    recording: Option<String>
+}
+
+impl <'a>ResponseWriter<'a> {
+    pub(crate) fn new(id: String, session: &'a WebSession) -> ResponseWriter<'a> {
+        return ResponseWriter { id: id, session: session, buffer: String::new(), pending: Vec::new(), recording: None }
+    }
+
+    /// appends the given text to the response, sending
+    /// full chunks to the frontend as they become available.
+    pub fn write_str(&mut self, text: &str) {
+        if let Some(ref mut recording) = self.recording {
+            recording.push_str(text);
+        }
+        for c in text.chars() {
+            // escapes are pushed whole, so they are never split between chunks
+            escape_char(c, &mut self.buffer);
+            if self.buffer.len() >= CHUNK_SIZE {
+                self.send_chunk(true);
+            }
+        }
+    }
+
+    /// starts keeping a copy of everything written from now on
+    pub(crate) fn record(&mut self) {
+        self.recording = Some(String::new());
+    }
+
+    /// returns everything written since `record()` was called
+    pub(crate) fn take_recording(&mut self) -> Option<String> {
+        self.recording.take()
+    }
+
+    fn send_chunk(&mut self, more: bool) {
+        let data = serde_json::to_string(&OkOrErrorResponse{ 
+            id: self.id.clone(), ok: true, message: std::mem::take(&mut self.buffer), more: more, encoding: None
+        }).unwrap();
+        log::trace!("Sending streamed chunk of length: {}", data.len());
+        send_message(self.session, &data);
+    }
+
+    /// sends the remainder of the response, marking it as complete
+    pub(crate) fn finish(mut self) {
+        self.send_chunk(false);
+    }
+}
+
+impl std::io::Write for ResponseWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid_len = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // an incomplete character at the end is completed by the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "response is not valid UTF-8"))
+        };
+        let bytes: Vec<u8> = self.pending.drain(..valid_len).collect();
+        self.write_str(std::str::from_utf8(&bytes).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
\ No newline at end of file
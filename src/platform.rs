@@ -0,0 +1,220 @@
+//! thin wrappers over platform FFI, which degrade gracefully
+//! when not running on the switch.
+
+use std::path::{Component, Path};
+use crate::response::{FilesystemInfo, MemoryInfo, TitleInfo};
+
+/// the mount point of the read-only romfs, where bundled assets live
+pub const ROMFS_ROOT: &str = "rom:/";
+
+/// the mount point of the SD card
+pub const SD_ROOT: &str = "sd:/";
+
+#[cfg(target_os = "switch")]
+mod ffi {
+    /// mirrors `nn::os::MemoryInfo`
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct MemoryInfo {
+        pub total_available_memory_size: i64,
+        pub total_used_memory_size: usize,
+        pub total_memory_heap_size: usize,
+        pub allocated_memory_heap_size: usize,
+        pub program_size: usize,
+        pub total_thread_stack_size: usize,
+        pub thread_count: i32,
+    }
+
+    extern "C" {
+        #[link_name = "_ZN2nn2os15QueryMemoryInfoEPNS0_10MemoryInfoE"]
+        pub fn QueryMemoryInfo(info: *mut MemoryInfo);
+    }
+}
+
+/// queries the current memory usage of the application
+#[cfg(target_os = "switch")]
+pub fn memory_info() -> MemoryInfo {
+    let mut info = ffi::MemoryInfo::default();
+    unsafe { ffi::QueryMemoryInfo(&mut info); }
+    let total = info.total_available_memory_size.max(0) as u64;
+    let used = info.total_used_memory_size as u64;
+    MemoryInfo {
+        supported: true,
+        total: total,
+        used: used,
+        available: total.saturating_sub(used),
+        heap_total: info.total_memory_heap_size as u64,
+        heap_allocated: info.allocated_memory_heap_size as u64
+    }
+}
+
+/// queries the current memory usage of the application
+#[cfg(not(target_os = "switch"))]
+pub fn memory_info() -> MemoryInfo {
+    MemoryInfo { supported: false, total: 0, used: 0, available: 0, heap_total: 0, heap_allocated: 0 }
+}
+
+/// closes the application entirely, returning to the home menu
+#[cfg(target_os = "switch")]
+pub fn exit_application() -> Result<String, String> {
+    unsafe { skyline::nn::oe::ExitApplication(); }
+    // the application is now closed, so this never returns
+}
+
+/// closes the application entirely, returning to the home menu
+#[cfg(not(target_os = "switch"))]
+pub fn exit_application() -> Result<String, String> {
+    Err("unsupported on this platform".to_string())
+}
+
+/// identifies the running title and its version
+#[cfg(target_os = "switch")]
+pub fn title_info() -> TitleInfo {
+    let mut version = skyline::nn::oe::DisplayVersion { name: [0; 16] };
+    unsafe { skyline::nn::oe::GetDisplayVersion(&mut version); }
+    let display_version: Vec<u8> = version.name.iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+    TitleInfo {
+        title_id: format!("{:016X}", skyline::info::get_program_id()),
+        display_version: match String::from_utf8(display_version) {
+            Ok(version) if !version.is_empty() => version,
+            _ => "unknown".to_string()
+        },
+        handler_version: env!("CARGO_PKG_VERSION").to_string()
+    }
+}
+
+/// identifies the running title and its version
+#[cfg(not(target_os = "switch"))]
+pub fn title_info() -> TitleInfo {
+    TitleInfo { 
+        title_id: "unknown".to_string(), 
+        display_version: "unknown".to_string(), 
+        handler_version: env!("CARGO_PKG_VERSION").to_string() 
+    }
+}
+
+/// returns the free space, in bytes, of the filesystem which the given 
+/// path is on (such as `sd:/`), if it can be determined.
+#[cfg(target_os = "switch")]
+pub fn free_space(path: &str) -> Option<u64> {
+    let mount = match path.find(":/") {
+        Some(index) => &path[..index + 2],
+        None => return None
+    };
+    let mut free: i64 = 0;
+    let result = unsafe { skyline::nn::fs::GetFreeSpaceSize(&mut free, [mount, "\0"].concat().as_ptr() as _) };
+    match result == 0 && free >= 0 {
+        true => Some(free as u64),
+        false => None
+    }
+}
+
+/// returns the free space, in bytes, of the filesystem which the given 
+/// path is on (such as `sd:/`), if it can be determined.
+#[cfg(not(target_os = "switch"))]
+pub fn free_space(_path: &str) -> Option<u64> {
+    None
+}
+
+/// the largest file a FAT32 filesystem can hold
+pub const FAT32_MAX_FILE_SIZE: u64 = 0xFFFF_FFFF;
+
+/// identifies the type of filesystem (such as `fat32` or `exfat`) which the 
+/// given path is on, if it can be determined. The switch doesn't expose this 
+/// for the SD card, so only the romfs is currently recognized.
+pub fn filesystem_type(path: &str) -> Option<String> {
+    if is_read_only(path) {
+        return Some("romfs".to_string());
+    }
+    None
+}
+
+/// the largest single file which the given filesystem type can hold, 
+/// if it is limited
+pub fn max_file_size(filesystem: &str) -> Option<u64> {
+    match filesystem {
+        "fat32" => Some(FAT32_MAX_FILE_SIZE),
+        _ => None
+    }
+}
+
+/// describes the filesystem which the given path is on. Where its type is unknown, 
+/// paths on the SD card are assumed to have FAT32's limits, since it may be FAT32 
+/// formatted, so that frontends can warn before writing a file over 4GB.
+pub fn filesystem_info(path: &str) -> FilesystemInfo {
+    let filesystem = filesystem_type(path);
+    let (max_file_size, limits_assumed) = match filesystem.as_deref() {
+        Some(filesystem) => (max_file_size(filesystem), false),
+        None if path.starts_with(SD_ROOT.trim_end_matches('/')) => (Some(FAT32_MAX_FILE_SIZE), true),
+        None => (None, false)
+    };
+    let limits_known = filesystem.is_some() || limits_assumed;
+    FilesystemInfo {
+        read_only: is_read_only(path),
+        free_space: free_space(path),
+        max_file_size: max_file_size,
+        supports_large_files: match limits_known {
+            true => Some(!matches!(max_file_size, Some(max) if max <= FAT32_MAX_FILE_SIZE)),
+            false => None
+        },
+        limits_assumed: limits_assumed,
+        filesystem: filesystem,
+        path: path.to_string()
+    }
+}
+
+/// reads the system clipboard. Neither the switch nor skyline 
+/// expose a clipboard, so this is currently always unsupported.
+pub fn clipboard_get() -> Result<String, String> {
+    Err("unsupported".to_string())
+}
+
+/// writes the system clipboard. Neither the switch nor skyline 
+/// expose a clipboard, so this is currently always unsupported.
+pub fn clipboard_set(_text: &str) -> Result<(), String> {
+    Err("unsupported".to_string())
+}
+
+/// resolves a path relative to the romfs root, rejecting any path which would 
+/// escape it. Returns `unsupported` where no romfs is mounted.
+pub fn romfs_path(relative: &str) -> Result<String, String> {
+    if !Path::new(ROMFS_ROOT).is_dir() {
+        return Err("unsupported".to_string());
+    }
+    let relative = relative.trim_start_matches(ROMFS_ROOT).trim_start_matches('/');
+    if Path::new(relative).components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("path {} is not within the romfs", relative));
+    }
+    Ok(format!("{}{}", ROMFS_ROOT, relative))
+}
+
+/// whether the given path is on a read-only filesystem (the romfs), 
+/// and so can never be written to or deleted.
+pub fn is_read_only(path: &str) -> bool {
+    path.starts_with(ROMFS_ROOT.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filesystem_info, FAT32_MAX_FILE_SIZE};
+
+    #[test]
+    fn assumes_fat32_limits_on_sd_card() {
+        let sd = filesystem_info("sd:/downloads/mod.zip");
+        assert_eq!(sd.filesystem, None);
+        assert_eq!(sd.max_file_size, Some(FAT32_MAX_FILE_SIZE));
+        assert_eq!(sd.supports_large_files, Some(false));
+        assert!(sd.limits_assumed);
+
+        let romfs = filesystem_info("rom:/assets/index.html");
+        assert_eq!(romfs.filesystem.as_deref(), Some("romfs"));
+        assert!(romfs.read_only && !romfs.limits_assumed);
+
+        let unknown = filesystem_info("/tmp/file");
+        assert_eq!(unknown.supports_large_files, None);
+        assert!(!unknown.limits_assumed);
+    }
+}
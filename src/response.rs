@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use crate::config::EngineConfig;
+use crate::sandbox::PathKind;
 use std::fmt;
 
 
@@ -17,19 +19,33 @@ impl fmt::Display for StringResponse {
     }
 }
 
-/// a basic boolean response
+/// a basic boolean response, shaped like an `OkOrErrorResponse`
+/// whose message is a JSON boolean rather than a string
 #[derive(Serialize, Deserialize)]
 pub struct BooleanResponse {
     pub id: String,
-    pub result: bool,
+    pub ok: bool,
+    pub message: bool,
+    pub more: bool,
 }
 
 impl fmt::Display for BooleanResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(id: {}, message: {})", self.id, self.result)
+        write!(f, "(id: {}, message: {})", self.id, self.message)
     }
 }
 
+/// an event pushed to the frontend for a topic it subscribed to, 
+/// rather than in response to any particular request
+#[derive(Serialize, Deserialize)]
+pub struct EventResponse {
+    /// always `event`, so the frontend can tell events apart from responses
+    pub id: String,
+    pub topic: String,
+    pub data: String,
+    pub more: bool,
+}
+
 /// a response that contains a flag for whether the
 /// operation was successful, as well as a message field.
 #[derive(Serialize, Deserialize)]
@@ -38,6 +54,10 @@ pub struct OkOrErrorResponse {
     pub ok: bool,
     pub message: String,
     pub more: bool,
+    /// how the (joined) message is encoded, if it isn't plain text. 
+    /// This is `gzip`, meaning gzipped and then base64-encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
 }
 
 impl fmt::Display for OkOrErrorResponse {
@@ -58,6 +78,28 @@ pub struct PathEntry {
     pub kind: i32
 }
 
+/**
+ * represents a batch of directory entries, pushed by `stream_list_dir`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct PathBatch {
+    /// the id of the `stream_list_dir` request which this batch belongs to
+    pub request_id: String,
+    /// the position of this batch, counting from 0
+    pub batch: usize,
+    pub list: Vec<PathEntry>
+}
+
+/**
+ * represents the next batch of entries read from a directory cursor
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DirEntries {
+    pub list: Vec<PathEntry>,
+    /// whether the directory has been read entirely (and the cursor closed)
+    pub done: bool
+}
+
 /**
  * represents a list of paths
  */
@@ -67,13 +109,525 @@ pub struct PathList {
 }
 
 /**
- * represents a directory tree
+ * represents a directory tree. `file_sizes` holds the size in bytes of
+ * each entry in `files` (by index), and `size` is the total size of
+ * everything contained in this directory, recursively.
  */
 #[derive(Serialize, Deserialize)]
 pub struct DirTree {
     pub name: String,
     pub dirs: Vec<DirTree>,
-    pub files: Vec<String>
+    pub files: Vec<String>,
+    pub file_sizes: Vec<u64>,
+    pub size: u64
+}
+
+impl DirTree {
+    pub fn new(name: String) -> Self {
+        return DirTree { name: name, dirs: Vec::new(), files: Vec::new(), file_sizes: Vec::new(), size: 0 }
+    }
+}
+
+/**
+ * represents the application's current memory budget, in bytes.
+ * If `supported` is false, the platform could not report the numbers.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct MemoryInfo {
+    pub supported: bool,
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+    pub heap_total: u64,
+    pub heap_allocated: u64
+}
+
+/**
+ * represents the result of a single request within a batch. If `ok`
+ * is true, `message` is the response body, otherwise it is the error.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct UrlResult {
+    pub url: String,
+    pub ok: bool,
+    pub message: String
+}
+
+/**
+ * represents an error which was returned to the frontend
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ErrorRecord {
+    pub call_name: String,
+    pub id: String,
+    pub message: String,
+    /// seconds since the unix epoch
+    pub timestamp: u64
+}
+
+/**
+ * represents a single line of a diff, where `kind` is one
+ * of `added`, `removed`, or `unchanged`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: String,
+    pub line: String
+}
+
+/**
+ * represents the outcome of merging one directory into another
+ */
+#[derive(Serialize, Deserialize)]
+pub struct MergeResult {
+    pub copied: u64,
+    pub skipped: u64,
+    pub overwritten: u64
+}
+
+/**
+ * represents the result of a connectivity check. `latency_ms`
+ * is how long the request took, whether or not it succeeded.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Connectivity {
+    pub url: String,
+    pub reachable: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>
+}
+
+/**
+ * describes a registered handler, and the number of arguments it expects
+ * (or `None` if its arguments are not validated)
+ */
+#[derive(Serialize, Deserialize)]
+pub struct HandlerInfo {
+    pub name: String,
+    pub arg_count: Option<usize>
+}
+
+/**
+ * describes what this backend supports, so that the frontend can adapt to it
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Capabilities {
+    /// the version of nx-request-handler
+    pub version: String,
+    pub handlers: Vec<HandlerInfo>,
+    /// the optional features which were compiled in
+    pub features: Vec<String>
+}
+
+/**
+ * represents one page of paths matching a search
+ */
+#[derive(Serialize, Deserialize)]
+pub struct SearchPage {
+    pub paths: Vec<String>,
+    pub page: usize,
+    pub page_size: usize,
+    /// whether there are more matches after this page
+    pub has_more: bool
+}
+
+/**
+ * represents a single handler invocation and how long it took
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OperationRecord {
+    pub call_name: String,
+    /// the arguments, with long values truncated
+    pub arguments: Vec<String>,
+    pub duration_ms: u64,
+    /// seconds since the unix epoch
+    pub timestamp: u64
+}
+
+/**
+ * represents the parts which a file was split into by `split_file`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct SplitResult {
+    /// the paths of the parts, in order
+    pub parts: Vec<String>,
+    /// the md5 of the whole file, to verify it once rejoined
+    pub md5: String
+}
+
+/**
+ * represents whether a path would be permitted by the sandbox
+ */
+#[derive(Serialize, Deserialize)]
+pub struct PathValidation {
+    pub path: String,
+    pub permitted: bool,
+    /// the normalized form of the path, which handlers will operate on
+    pub canonical: Option<String>,
+    /// why the path is not permitted, if it isn't
+    pub reason: Option<String>
+}
+
+/**
+ * represents a best-effort answer to whether an operation is possible
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Feasibility {
+    pub possible: bool,
+    pub reason: String
+}
+
+/**
+ * represents the title which the plugin is running in
+ */
+#[derive(Serialize, Deserialize)]
+pub struct TitleInfo {
+    /// the program id, in hex, or `unknown`
+    pub title_id: String,
+    /// the application's display version (such as `13.0.1`), or `unknown`
+    pub display_version: String,
+    /// the version of nx-request-handler
+    pub handler_version: String
+}
+
+/**
+ * represents a single file of a manifest written by `export_manifest`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// the path of the file, relative to the manifest's directory
+    pub path: String,
+    pub size: u64,
+    pub md5: String
+}
+
+/**
+ * represents a manifest written by `export_manifest`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>
+}
+
+/**
+ * represents how a directory differs from a manifest
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ManifestDiff {
+    /// files which are not in the manifest
+    pub added: Vec<String>,
+    /// files in the manifest which no longer exist
+    pub removed: Vec<String>,
+    /// files whose size or hash differs from the manifest
+    pub modified: Vec<String>
 }
 
+/**
+ * represents whether an archive will fit at its install destination
+ */
+#[derive(Serialize, Deserialize)]
+pub struct InstallCheck {
+    /// the number of files in the archive
+    pub file_count: usize,
+    /// the space needed to extract the archive, in bytes, including cluster overhead
+    pub required: u64,
+    /// the free space at the destination, in bytes, if known
+    pub free: Option<u64>,
+    /// the space which would remain after installing, if known (negative if it doesn't fit)
+    pub headroom: Option<i64>,
+    /// whether the archive fits (assumed if the free space is unknown)
+    pub fits: bool
+}
+
+/**
+ * represents a file's contents along with its metadata
+ */
+#[derive(Serialize, Deserialize)]
+pub struct FileContents {
+    pub content: String,
+    /// how `content` is encoded: `utf8`, or `base64` for binary files
+    pub encoding: String,
+    /// the size of the whole file, in bytes
+    pub size: u64,
+    /// the modified time, in seconds since the unix epoch
+    pub modified: u64,
+    /// the md5 of the whole file
+    pub md5: String,
+    /// whether `content` holds only the start of the file, because it is too large
+    pub partial: bool
+}
+
+/**
+ * represents a text file's contents, decoded according to its byte order mark
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DecodedText {
+    /// the contents, without the byte order mark
+    pub content: String,
+    /// `UTF-8`, `UTF-16LE`, or `UTF-16BE`
+    pub encoding: String,
+    /// whether the file started with a byte order mark
+    pub bom: bool
+}
+
+/**
+ * represents a path argument which was rejected by safe mode
+ */
+#[derive(Serialize, Deserialize)]
+pub struct PathError {
+    /// `not_found`, `not_a_file`, or `not_a_directory`
+    pub error: String,
+    pub path: String,
+    /// the index of the offending argument
+    pub argument: usize
+}
+
+/**
+ * represents how the last attempt to shut down the engine went
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ShutdownStatus {
+    /// `clean`, `timed_out` if closing the session took longer than expected,
+    /// or `errored` if the session could not be closed normally
+    pub outcome: String,
+    /// how long closing the session took, in milliseconds
+    pub duration_ms: u64,
+    /// what went wrong, if the shutdown was not clean
+    pub error: Option<String>,
+    /// seconds since the unix epoch
+    pub timestamp: u64
+}
+
+/**
+ * represents a group of identical files, found by `find_duplicates`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub md5: String,
+    /// the size of each file, in bytes
+    pub size: u64,
+    pub paths: Vec<String>
+}
+
+/**
+ * represents the storage throughput measured by `benchmark_io`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct IoBenchmark {
+    /// the size of the file which was written and read back, in bytes
+    pub size: u64,
+    pub write_ms: u64,
+    pub read_ms: u64,
+    /// megabytes (of 1,000,000 bytes) per second
+    pub write_mb_per_sec: f64,
+    pub read_mb_per_sec: f64
+}
+
+/**
+ * represents a request which was rejected for having oversized arguments
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ArgumentSizeError {
+    /// `argument_too_large` or `arguments_too_large` (for the total)
+    pub error: String,
+    /// the index of the offending argument, if a single argument was too large
+    pub argument: Option<usize>,
+    /// the size of the argument (or of all the arguments), in bytes
+    pub size: usize,
+    /// the limit which was exceeded, in bytes
+    pub limit: usize
+}
+
+/**
+ * represents everything which is useful for diagnosing a problem, gathered by `support_bundle`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct SupportBundle {
+    /// seconds since the unix epoch
+    pub generated: u64,
+    pub capabilities: Capabilities,
+    pub title: TitleInfo,
+    pub memory: MemoryInfo,
+    /// the free space on the SD card, in bytes, if known
+    pub sd_free_space: Option<u64>,
+    pub config: EngineConfig,
+    /// the recent errors, if error history is enabled, with urls' query strings redacted
+    pub recent_errors: Vec<ErrorRecord>,
+    /// the slowest operations, if tracked, with urls' query strings redacted
+    pub slow_operations: Vec<OperationRecord>,
+    pub last_shutdown: Option<ShutdownStatus>
+}
+
+/**
+ * represents the outcome of `download_and_verify`. If it failed, `failed_step` is one of
+ * `checksum_download`, `checksum_parse`, `download`, `verify`, or `move`, and `error` says why.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct VerifiedDownload {
+    pub path: String,
+    /// `md5` or `sha256`, once known from the checksum file
+    pub algorithm: Option<String>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub failed_step: Option<String>,
+    pub error: Option<String>
+}
 
+/**
+ * represents the outcome of `compress_file` or `decompress_file`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct CompressionResult {
+    pub input_size: u64,
+    pub output_size: u64,
+    /// the compressed size divided by the uncompressed size
+    pub ratio: f64
+}
+
+/**
+ * represents text appended to a file followed with `follow_file`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct FollowUpdate {
+    pub follow_id: String,
+    pub text: String,
+    /// whether the file was truncated or replaced, and is being read from its start again
+    pub reset: bool
+}
+
+/**
+ * represents whether a zip looks safe to extract, as checked by `check_zip_safety`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ZipSafetyReport {
+    /// whether the archive is within every limit, and has no unsafe paths
+    pub safe: bool,
+    pub entry_count: usize,
+    /// the total uncompressed size, in bytes
+    pub total_size: u64,
+    /// the highest compression ratio of any (large enough) entry
+    pub max_ratio: f64,
+    /// entries whose names would extract outside of the destination
+    pub unsafe_paths: Vec<String>,
+    /// a description of each limit which was exceeded
+    pub problems: Vec<String>
+}
+
+/**
+ * represents a request received from the frontend, as recorded for `get_call_history`
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CallRecord {
+    pub id: String,
+    pub call_name: String,
+    /// the arguments, with long values truncated
+    pub arguments: Option<Vec<String>>,
+    /// whether any argument was truncated, which prevents the call from being replayed
+    pub truncated: bool,
+    /// seconds since the unix epoch
+    pub timestamp: u64
+}
+
+/**
+ * represents the filesystem which a path is on, as returned by `filesystem_info`. 
+ * Fields are `None` where the platform could not report them.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct FilesystemInfo {
+    pub path: String,
+    /// such as `fat32`, `exfat` or `romfs`
+    pub filesystem: Option<String>,
+    pub read_only: bool,
+    pub free_space: Option<u64>,
+    /// the largest single file which the filesystem can hold, if known to be limited
+    pub max_file_size: Option<u64>,
+    /// whether files over 4GB can be written, if known
+    pub supports_large_files: Option<bool>,
+    /// whether the limits are assumed rather than known. The SD card may be formatted as 
+    /// FAT32 or exFAT, which can't be told apart, so FAT32's stricter limits are assumed.
+    pub limits_assumed: bool
+}
+
+/**
+ * describes a registered handler in enough detail to generate typed bindings for it
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HandlerSchema {
+    pub name: String,
+    /// the number of arguments expected, or `None` if they are not validated
+    pub arg_count: Option<usize>,
+    /// what the handler responds with: `string`, `boolean` or `stream` (a string sent in parts)
+    pub returns: String,
+    /// the arguments which are paths, and what they must refer to
+    pub path_arguments: Vec<PathArgumentSchema>
+}
+
+/**
+ * describes a path argument of a handler
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PathArgumentSchema {
+    pub index: usize,
+    pub kind: PathKind
+}
+
+/**
+ * describes every registered handler, as returned by `get_schema`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Schema {
+    /// the version of nx-request-handler
+    pub version: String,
+    pub handlers: Vec<HandlerSchema>
+}
+
+/**
+ * represents the engine's current settings, as returned by `get_config`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct EngineSettings {
+    /// the version of nx-request-handler
+    pub version: String,
+    /// the most characters sent to the frontend in a single message, beyond 
+    /// which responses are split into chunks
+    pub chunk_size: usize,
+    /// the configuration, with defaults filled in for unset settings
+    pub config: EngineConfig
+}
+
+/**
+ * represents a file and its size in bytes
+ */
+#[derive(Serialize, Deserialize)]
+pub struct FileSize {
+    pub path: String,
+    pub size: u64
+}
+
+/**
+ * represents the files with one extension within a directory, as part of `DirStats`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ExtensionStats {
+    /// the lowercase extension without its dot, or empty for files without one
+    pub extension: String,
+    pub size: u64,
+    pub count: usize
+}
+
+/**
+ * represents the aggregate disk usage of a directory, as returned by `dir_stats`
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DirStats {
+    pub path: String,
+    pub total_size: u64,
+    pub file_count: usize,
+    pub dir_count: usize,
+    /// the largest files, largest first
+    pub largest: Vec<FileSize>,
+    /// the bytes used by each extension, largest first
+    pub extensions: Vec<ExtensionStats>,
+    /// how many entries could not be read, and so are not counted
+    pub skipped: usize
+}
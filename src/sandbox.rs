@@ -0,0 +1,168 @@
+//! path normalization, the optional sandbox which restricts handlers' path 
+//! arguments to the configured directories, and safe mode's path checks.
+
+use std::path::Path;
+use crate::config::EngineConfig;
+use crate::response::PathError;
+use serde::{Deserialize, Serialize};
+
+/// what a handler's path argument must refer to before the handler 
+/// is called, when safe mode is enabled (see `with_safe_mode()`)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+    /// anything, including nothing (such as the destination of a write)
+    Any,
+    /// an existing file or directory
+    Exists,
+    /// an existing file
+    File,
+    /// an existing directory
+    Dir
+}
+
+/// checks that the given path argument refers to what it must, returning a 
+/// uniform, structured error describing the problem if it does not.
+pub fn check_kind(path: &str, index: usize, kind: PathKind) -> Result<(), PathError> {
+    let path_ref = Path::new(path);
+    let error = match kind {
+        PathKind::Any => return Ok(()),
+        _ if !path_ref.exists() => "not_found",
+        PathKind::File if !path_ref.is_file() => "not_a_file",
+        PathKind::Dir if !path_ref.is_dir() => "not_a_directory",
+        _ => return Ok(())
+    };
+    Err(PathError { error: error.to_string(), path: path.to_string(), argument: index })
+}
+
+/// normalizes a path lexically (without touching the filesystem, since the path
+/// may not exist yet), unifying separators and resolving `.` and `..` components.
+/// Paths which would escape their root via `..` are rejected.
+pub fn normalize_path(path: &str) -> Result<String, String> {
+    let path = path.trim().replace('\\', "/");
+    // keep the mount name (such as `sd:`) apart, so that it can't be popped
+    let (root, rest) = match path.find(":/") {
+        Some(index) => (format!("{}/", &path[..index + 1]), &path[index + 2..]),
+        None if path.starts_with('/') => ("/".to_string(), &path[1..]),
+        None => (String::new(), &path[..])
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('/') {
+        match component {
+            "" | "." => {},
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(format!("path {} escapes its root", path));
+                }
+            },
+            _ => components.push(component)
+        }
+    }
+    Ok(format!("{}{}", root, components.join("/")))
+}
+
+/// resolves the given existing path to its canonical form, following any symlinks. Where
+/// the platform can't canonicalize paths (such as on the switch, whose SD card has no symlinks
+/// anyway), the path is normalized lexically instead.
+pub fn real_path(path: &str) -> Result<String, String> {
+    // canonicalize's error for a missing path differs between platforms, so check first
+    if !Path::new(path).exists() {
+        return Err(format!("path {} does not exist!", path));
+    }
+    match std::fs::canonicalize(path) {
+        Ok(canonical) => {
+            let canonical = canonical.display().to_string().replace('\\', "/");
+            // windows marks canonical paths as verbatim, which nothing else expects
+            Ok(canonical.strip_prefix("//?/").map(|stripped| stripped.to_string()).unwrap_or(canonical))
+        },
+        Err(_) => normalize_path(path)
+    }
+}
+
+/// whether the two given existing paths refer to the same underlying file, 
+/// such as through a hardlink, a symlink, or differently written paths.
+pub fn same_file(first: &str, second: &str) -> Result<bool, String> {
+    let first_real = real_path(first)?;
+    let second_real = real_path(second)?;
+    if first_real == second_real {
+        return Ok(true);
+    }
+    // hardlinks have different paths, but share their device and inode
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(first_md), Ok(second_md)) = (std::fs::metadata(first), std::fs::metadata(second)) {
+            return Ok(first_md.dev() == second_md.dev() && first_md.ino() == second_md.ino());
+        }
+    }
+    Ok(false)
+}
+
+/// returns the longest directory path which all of the given normalized 
+/// paths are within, or an empty string if they share nothing (such as 
+/// being on different mounts) or no paths are given.
+pub fn common_base(paths: &[String]) -> String {
+    let mut paths = paths.iter();
+    let mut base: Vec<&str> = match paths.next() {
+        Some(first) => first.split('/').collect(),
+        None => return String::new()
+    };
+    for path in paths {
+        let common = base.iter().zip(path.split('/')).take_while(|(a, b)| *a == b).count();
+        base.truncate(common);
+    }
+    return match base.as_slice() {
+        // the root of a mount keeps its trailing slash, as in `sd:/`
+        [root] if root.ends_with(':') => format!("{}/", root),
+        [""] => "/".to_string(),
+        _ => base.join("/")
+    }
+}
+
+/// checks the given path against the configured sandbox, returning its
+/// normalized form if it is permitted, or the reason it is not.
+pub fn check_path(config: &EngineConfig, path: &str) -> Result<String, String> {
+    let normalized = normalize_path(path)?;
+    if config.sandbox_roots.is_empty() {
+        return Ok(normalized);
+    }
+    for root in &config.sandbox_roots {
+        let root = normalize_path(root)?;
+        let root = root.trim_end_matches('/');
+        if normalized == root || normalized.starts_with(&format!("{}/", root)) {
+            return Ok(normalized);
+        }
+    }
+    Err(format!("path {} is outside of the permitted directories", normalized))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_path, common_base, normalize_path};
+    use crate::config::EngineConfig;
+
+    #[test]
+    fn normalizes_and_confines_paths() {
+        assert_eq!(normalize_path("sd:/ultimate//mods/./hdr/../hdr-assets/").unwrap(), "sd:/ultimate/mods/hdr-assets");
+        assert_eq!(normalize_path("sd:\\ultimate\\mods").unwrap(), "sd:/ultimate/mods");
+        assert!(normalize_path("sd:/ultimate/../..").is_err());
+
+        let config = EngineConfig { sandbox_roots: vec!["sd:/ultimate/mods/".to_string()], ..Default::default() };
+        assert_eq!(check_path(&config, "sd:/ultimate/mods").unwrap(), "sd:/ultimate/mods");
+        assert!(check_path(&config, "sd:/ultimate/mods/hdr/file.txt").is_ok());
+        assert!(check_path(&config, "sd:/ultimate/mods/../saves").is_err());
+        assert!(check_path(&config, "sd:/ultimate/mods-other").is_err());
+    }
+
+    #[test]
+    fn finds_common_base() {
+        let paths = |paths: &[&str]| paths.iter().map(|path| path.to_string()).collect::<Vec<String>>();
+        assert_eq!(common_base(&paths(&["sd:/ultimate/mods/hdr", "sd:/ultimate/mods/hdr-assets"])), "sd:/ultimate/mods");
+        assert_eq!(common_base(&paths(&["sd:/ultimate/mods", "sd:/ultimate/mods/hdr"])), "sd:/ultimate/mods");
+        assert_eq!(common_base(&paths(&["sd:/ultimate", "sd:/atmosphere"])), "sd:/");
+        assert_eq!(common_base(&paths(&["sd:/ultimate", "rom:/ultimate"])), "");
+        assert_eq!(common_base(&paths(&["sd:/ultimate"])), "sd:/ultimate");
+        assert_eq!(common_base(&[]), "");
+    }
+}
@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use serde::{Serialize, Deserialize};
+use crate::response::{CallRecord, ErrorRecord, HandlerSchema, OperationRecord, ShutdownStatus};
+use crate::jobs::JobRegistry;
+use crate::concurrency::{BusyCounter, FileLocks};
+use std::sync::Arc;
+
+/// the file in the working directory which remembers how the last shutdown went,
+/// so that `get_shutdown_status` can report it after the plugin is reloaded
+const SHUTDOWN_STATUS_FILE: &str = "last_shutdown.json";
+
+/// arguments longer than this are truncated when recorded, 
+/// since they may hold entire file contents
+const MAX_RECORDED_ARGUMENT_LENGTH: usize = 64;
+
+/// how long a directory cursor (see `open_dir`) may go unused before it is closed
+const DIR_CURSOR_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// runtime state of the `RequestEngine`, which handlers
+/// can read and update via the `MessageContext`.
+#[derive(Default)]
+pub struct EngineState {
+    /// the most recent errors returned to the frontend, oldest first
+    pub recent_errors: VecDeque<ErrorRecord>,
+    /// raw messages which arrived while another request was being handled,
+    /// waiting to be handled in order
+    pub pending_messages: RefCell<VecDeque<String>>,
+    /// a description of every registered handler, by name
+    pub handler_schemas: BTreeMap<String, HandlerSchema>,
+    /// the slowest handler invocations so far, slowest first
+    pub slow_operations: Vec<OperationRecord>,
+    /// the known hash of each file hashed by `dir_hash`, by path. This is
+    /// `None` until first used, since it may need to be loaded from disk.
+    pub hash_cache: Option<HashMap<String, CachedHash>>,
+    /// the working directory chosen with `set_config_dir`, which 
+    /// takes precedence over the configured one
+    pub config_dir: Option<String>,
+    /// cached responses of handlers with a cache ttl, by handler name and then arguments
+    pub result_cache: HashMap<String, HashMap<Vec<String>, CachedResult>>,
+    /// background jobs, and their progress
+    pub jobs: JobRegistry,
+    /// temporary files and directories created with `make_temp_file`/`make_temp_dir`,
+    /// which are removed when the engine shuts down
+    pub temp_paths: Vec<String>,
+    /// how many downloads batch handlers (such as `get_requests`) run at once. 
+    /// A value of 0 means the default of one at a time.
+    pub download_concurrency: usize,
+    /// the event topics which the frontend has subscribed to
+    pub subscriptions: BTreeSet<String>,
+    /// how the last attempt to shut down the engine went, if there was one
+    pub last_shutdown: Option<ShutdownStatus>,
+    /// the directories opened with `open_dir`, by handle
+    pub dir_cursors: HashMap<String, DirCursor>,
+    /// the number of the last directory cursor opened, for making handles
+    pub last_dir_cursor: u64,
+    /// advisory locks on files being updated, see `MessageContext::lock_file()`
+    pub file_locks: Arc<FileLocks>,
+    /// the files being followed with `follow_file`, by follow id
+    pub follows: BTreeMap<String, FollowedFile>,
+    /// the number of the last follow started, for making follow ids
+    pub last_follow: u64,
+    /// the most recent results, oldest first, by request id, for `get_result`
+    pub recent_results: VecDeque<(String, StoredResult)>,
+    /// the most recent requests, oldest first, for `get_call_history`
+    pub recent_calls: VecDeque<CallRecord>,
+    /// the handlers which are currently running
+    pub busy: BusyCounter,
+}
+
+/// the hash of a file, along with the metadata it was computed for. The 
+/// hash is reused for as long as the file's size and modified time match.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedHash {
+    pub size: u64,
+    /// the modified time, in milliseconds since the unix epoch
+    pub modified: u64,
+    pub md5: String
+}
+
+/// a handler's successful response, as cached for `set_cache_ttl()`
+#[derive(Clone)]
+pub enum CachedResponse {
+    Text(String),
+    Bool(bool),
+    /// the full text which a streaming handler wrote
+    Streamed(String)
+}
+
+pub struct CachedResult {
+    pub response: CachedResponse,
+    pub expires: Instant
+}
+
+/// the result of a request, as kept for `get_result`
+pub struct StoredResult {
+    pub result: Result<CachedResponse, String>,
+    pub expires: Instant
+}
+
+/// a file being followed with `follow_file`
+pub struct FollowedFile {
+    pub path: String,
+    /// how much of the file has been sent already
+    pub offset: u64
+}
+
+/// a directory being read a batch at a time, see `open_dir`
+pub struct DirCursor {
+    pub entries: std::fs::ReadDir,
+    pub last_used: Instant
+}
+
+impl EngineState {
+    /// removes every temporary file and directory created during this session
+    pub fn remove_temp_paths(&mut self) {
+        for path in self.temp_paths.drain(..) {
+            let path = std::path::Path::new(&path);
+            let _ = match path.is_dir() {
+                true => std::fs::remove_dir_all(path),
+                false => std::fs::remove_file(path)
+            };
+        }
+    }
+
+    /// opens a cursor over the given directory, returning its handle.
+    /// Cursors which have gone unused for too long are closed first.
+    pub fn open_dir_cursor(&mut self, entries: std::fs::ReadDir) -> String {
+        self.dir_cursors.retain(|_, cursor| cursor.last_used.elapsed() < DIR_CURSOR_TIMEOUT);
+        self.last_dir_cursor += 1;
+        let handle = format!("dir-{}", self.last_dir_cursor);
+        self.dir_cursors.insert(handle.clone(), DirCursor { entries: entries, last_used: Instant::now() });
+        handle
+    }
+
+    /// returns the cached response for the given request, if it has not expired yet
+    pub fn cached_response(&mut self, call_name: &str, arguments: &[String]) -> Option<CachedResponse> {
+        let cache = self.result_cache.get_mut(call_name)?;
+        let now = Instant::now();
+        cache.retain(|_, cached| cached.expires > now);
+        cache.get(arguments).map(|cached| cached.response.clone())
+    }
+
+    /// caches the response to the given request for `ttl`
+    pub fn cache_response(&mut self, call_name: &str, arguments: Vec<String>, response: CachedResponse, ttl: Duration) {
+        self.result_cache.entry(call_name.to_string())
+            .or_default()
+            .insert(arguments, CachedResult { response: response, expires: Instant::now() + ttl });
+    }
+
+    /// keeps the result of the request with the given id for `ttl`, 
+    /// forgetting the oldest results beyond `capacity`
+    pub fn store_result(&mut self, id: &str, result: Result<CachedResponse, String>, capacity: usize, ttl: Duration) {
+        let now = Instant::now();
+        self.recent_results.retain(|(existing, stored)| existing != id && stored.expires > now);
+        self.recent_results.push_back((id.to_string(), StoredResult { result: result, expires: now + ttl }));
+        while self.recent_results.len() > capacity {
+            self.recent_results.pop_front();
+        }
+    }
+
+    /// returns the kept result of the request with the given id, if it has not expired yet
+    pub fn stored_result(&self, id: &str) -> Option<&Result<CachedResponse, String>> {
+        let now = Instant::now();
+        self.recent_results.iter()
+            .find(|(existing, stored)| existing == id && stored.expires > now)
+            .map(|(_, stored)| &stored.result)
+    }
+
+    /// records a request, keeping only the `capacity` most recent
+    pub fn record_call(&mut self, id: &str, call_name: &str, arguments: &Option<Vec<String>>, capacity: usize) {
+        let sanitized = sanitize_arguments(arguments);
+        let truncated = arguments.as_ref().map(|args| *args != sanitized).unwrap_or(false);
+        self.recent_calls.push_back(CallRecord {
+            id: id.to_string(),
+            call_name: call_name.to_string(),
+            arguments: arguments.as_ref().map(|_| sanitized),
+            truncated: truncated,
+            timestamp: unix_timestamp()
+        });
+        while self.recent_calls.len() > capacity {
+            self.recent_calls.pop_front();
+        }
+    }
+
+    /// records a handler invocation, keeping only the `capacity` slowest
+    pub fn record_operation(&mut self, record: OperationRecord, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        let index = self.slow_operations.iter()
+            .position(|existing| existing.duration_ms < record.duration_ms)
+            .unwrap_or(self.slow_operations.len());
+        if index < capacity {
+            self.slow_operations.insert(index, record);
+            self.slow_operations.truncate(capacity);
+        }
+    }
+
+    /// remembers how the shutdown went, persisting it to the given working directory
+    pub(crate) fn record_shutdown(&mut self, config_dir: &str, status: ShutdownStatus) {
+        let path = std::path::Path::new(config_dir).join(SHUTDOWN_STATUS_FILE);
+        let result = std::fs::create_dir_all(config_dir)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string(&status).unwrap_or_default()));
+        if let Err(e) = result {
+            log::warn!("Could not persist the shutdown status to {}. Reason: {:?}", path.display(), e);
+        }
+        self.last_shutdown = Some(status);
+    }
+}
+
+/// reads how the last shutdown went from the given working directory, if it was recorded
+pub(crate) fn load_shutdown_status(config_dir: &str) -> Option<ShutdownStatus> {
+    let path = std::path::Path::new(config_dir).join(SHUTDOWN_STATUS_FILE);
+    return match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).ok(),
+        Err(_) => None
+    }
+}
+
+/// shortens long arguments, so that recorded requests stay small 
+/// and don't retain potentially sensitive file contents.
+pub(crate) fn sanitize_arguments(arguments: &Option<Vec<String>>) -> Vec<String> {
+    return match arguments {
+        Some(args) => args.iter().map(|arg| {
+            match arg.char_indices().nth(MAX_RECORDED_ARGUMENT_LENGTH) {
+                Some((end, _)) => format!("{}... ({} bytes)", &arg[..end], arg.len()),
+                None => arg.clone()
+            }
+        }).collect(),
+        None => Vec::new()
+    }
+}
+
+/// the current time, in seconds since the unix epoch
+pub(crate) fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
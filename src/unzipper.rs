@@ -1,11 +1,15 @@
 use std::{io::{BufReader, Read, Seek, SeekFrom}};
 
+/// a file read through the switch's own filesystem api, which 
+/// is considerably faster than `std::fs` for large archives
+#[cfg(target_os = "switch")]
 pub struct NintendoFile {
     handle: nn::fs::FileHandle,
     offset: isize,
     file_size: isize,
 }
 
+#[cfg(target_os = "switch")]
 impl NintendoFile {
     pub fn open(path: &str) -> std::io::Result<Self> {
         std::thread::sleep(std::time::Duration::from_millis(100));
@@ -34,6 +38,7 @@ impl NintendoFile {
     }
 }
 
+#[cfg(target_os = "switch")]
 impl Drop for NintendoFile {
     fn drop(&mut self) {
         unsafe {
@@ -42,6 +47,7 @@ impl Drop for NintendoFile {
     }
 }
 
+#[cfg(target_os = "switch")]
 impl Read for NintendoFile {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         // println!("Reading {} bytes!", buf.len());
@@ -58,6 +64,7 @@ impl Read for NintendoFile {
     }
 }
 
+#[cfg(target_os = "switch")]
 impl Seek for NintendoFile {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         // println!("Seeking {:?}", pos);
@@ -75,6 +82,34 @@ impl Seek for NintendoFile {
     }
 }
 
+/// off the switch, there is no `nn::fs`, so files are read through `std::fs` instead
+#[cfg(not(target_os = "switch"))]
+pub struct NintendoFile {
+    file: std::fs::File
+}
+
+#[cfg(not(target_os = "switch"))]
+impl NintendoFile {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        Ok(Self { file: std::fs::File::open(path)? })
+    }
+}
+
+#[cfg(not(target_os = "switch"))]
+impl Read for NintendoFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(not(target_os = "switch"))]
+impl Seek for NintendoFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(target_os = "switch")]
 use skyline::nn;
 use zip::{result::ZipResult, ZipArchive};
 